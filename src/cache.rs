@@ -0,0 +1,402 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    process::{self, exit},
+    time::{Duration, SystemTime},
+};
+
+use clap::Subcommand;
+use image::{imageops::FilterType, DynamicImage, ImageFormat, ImageReader};
+
+use crate::crop::{crop_ratio, region};
+use crate::effects::{apply, ImageEffect};
+use crate::images::dimensions;
+use crate::index::fnv_hash;
+use crate::quotes::Color;
+use crate::settings::{project_dirs, NerdcliConfig};
+
+/// Size limit of the thumbnail cache, if `thumbnail_cache_mb` is not set.
+const DEFAULT_CACHE_MB: u64 = 100;
+
+/// Pixels of a terminal cell, if the terminal does not report its size in pixels.
+const DEFAULT_CELL_WIDTH_PX: u32 = 10;
+
+/// Copies written while the cache is disabled are deleted by later runs after this time.
+const UNCACHED_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Subcommand)]
+pub enum CacheCommand {
+    #[command(
+        about = "Delete all cached thumbnails and cropped copies (the image index is rebuilt by `nerdcli index rebuild`)"
+    )]
+    Clear,
+
+    #[command(about = "Print the location and the size of the cache")]
+    Status,
+}
+
+pub fn run(command: &CacheCommand) {
+    let dir = project_dirs().cache_dir().to_path_buf();
+    match command {
+        CacheCommand::Clear => {
+            let mut cleared = false;
+            for copies in [thumbnail_dir(), uncached_dir()] {
+                match fs::remove_dir_all(&copies) {
+                    Ok(_) => cleared = true,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        eprintln!("Could not clear the cache `{}`: {}", copies.display(), e);
+                        exit(1);
+                    }
+                }
+            }
+            match cleared {
+                true => println!(
+                    "Cleared the thumbnails and cropped copies in `{}`.",
+                    dir.display()
+                ),
+                false => println!("The cache is empty."),
+            }
+        }
+        CacheCommand::Status => {
+            let thumbnails = cached_thumbnails(&thumbnail_dir());
+            let size: u64 = thumbnails.iter().map(|(_, size, _)| size).sum();
+            println!("Cache directory: {}", dir.display());
+            println!(
+                "Thumbnails: {} ({:.1} MB)",
+                thumbnails.len(),
+                size as f64 / 1024.0 / 1024.0
+            );
+        }
+    }
+}
+
+fn thumbnail_dir() -> PathBuf {
+    project_dirs().cache_dir().join("thumbnails")
+}
+
+/// The copies of the images shown while the cache is disabled.
+fn uncached_dir() -> PathBuf {
+    project_dirs().cache_dir().join("uncached")
+}
+
+/// The path of a copy that is only used by this process, for when the cache is disabled. It is in
+/// the cache dir of the user, so other users can't replace it, and named after the process, so
+/// nerdcli in another terminal doesn't overwrite it before it is shown.
+fn uncached_path(name: &str) -> PathBuf {
+    let dir = uncached_dir();
+    for (path, _, modified) in cached_thumbnails(&dir) {
+        if modified.elapsed().is_ok_and(|age| age > UNCACHED_MAX_AGE) {
+            let _ = fs::remove_file(path);
+        }
+    }
+    dir.join(format!("{}-{}.png", name, process::id()))
+}
+
+/// The file an image is written to before it is renamed to `path`, unique per process, so a
+/// concurrent run never renames a half-written file.
+fn temp_path(path: &Path) -> PathBuf {
+    path.with_extension(format!("{}.tmp", process::id()))
+}
+
+/// Returns the path of a downscaled copy of the image for the given size in terminal cells and
+/// graphics protocol, so the full image only needs to be decoded the first time.
+/// Falls back to the original image if the cache is disabled or the thumbnail can't be created.
+pub fn thumbnail(
+    image: &Path,
+    width_cells: Option<u32>,
+    height_cells: Option<u32>,
+    protocol: &str,
+    config: &NerdcliConfig,
+) -> PathBuf {
+    let limit_mb = config.thumbnail_cache_mb.unwrap_or(DEFAULT_CACHE_MB);
+    if limit_mb == 0 || width_cells.unwrap_or(0) == 0 {
+        return image.to_path_buf();
+    }
+
+    match cached_thumbnail(image, width_cells, height_cells, protocol, limit_mb) {
+        Ok(Some(path)) => path,
+        Ok(None) => image.to_path_buf(),
+        Err(e) => {
+            eprintln!(
+                "Could not create a thumbnail of `{}`: {}",
+                image.display(),
+                e
+            );
+            image.to_path_buf()
+        }
+    }
+}
+
+fn cached_thumbnail(
+    image: &Path,
+    width_cells: Option<u32>,
+    height_cells: Option<u32>,
+    protocol: &str,
+    limit_mb: u64,
+) -> Result<Option<PathBuf>, String> {
+    // the key changes with the image file, the size in the terminal and the protocol
+    let key = format!(
+        "{}|{}|{}|{}",
+        file_key(image)?,
+        width_cells.unwrap_or(0),
+        height_cells.unwrap_or(0),
+        protocol
+    );
+    let dir = thumbnail_dir();
+    let path = dir.join(format!("{}.png", fnv_hash(key.as_bytes())));
+    if is_cached(&path) {
+        return Ok(Some(path));
+    }
+
+    // block characters show one pixel per cell in width (and two in height),
+    // graphic protocols show the pixels of the cell
+    let pixels_per_cell = match protocol {
+        "blocks" => 1,
+        _ => cell_width_px(),
+    };
+    let target_width = width_cells.unwrap_or(0) * pixels_per_cell;
+    let target_height = height_cells.map_or(u32::MAX, |h| h * pixels_per_cell * 2);
+    let (original_width, _) = dimensions(image).map_err(|e| e.to_string())?;
+    if target_width >= original_width {
+        // nothing to gain, the original is small enough
+        return Ok(None);
+    }
+
+    let img = decode(image)?;
+
+    let resized = img.resize(target_width, target_height, FilterType::Triangle);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let tmp = temp_path(&path);
+    resized
+        .save_with_format(&tmp, ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    fs::rename(&tmp, &path).map_err(|e| e.to_string())?;
+
+    shrink_cache(&dir, limit_mb * 1024 * 1024, &path);
+    Ok(Some(path))
+}
+
+fn decode(image: &Path) -> Result<DynamicImage, String> {
+    ImageReader::open(image)
+        .map_err(|e| e.to_string())?
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .decode()
+        .map_err(|e| e.to_string())
+}
+
+/// Identifies the version of an image file by its path, modification time and size.
+fn file_key(image: &Path) -> Result<String, String> {
+    // cached copies are named after their source, their modification time is their last use
+    if image.starts_with(thumbnail_dir()) {
+        return Ok(image.display().to_string());
+    }
+    let metadata = fs::metadata(image).map_err(|e| e.to_string())?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    Ok(format!(
+        "{}|{}|{}",
+        image.display(),
+        modified,
+        metadata.len()
+    ))
+}
+
+/// Checks if a file is in the cache and remembers its last use for the LRU cleanup.
+fn is_cached(path: &Path) -> bool {
+    if !path.exists() {
+        return false;
+    }
+    if let Ok(file) = File::options().append(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+    true
+}
+
+/// Returns the path of a copy of the image cropped to the aspect ratio `target_ratio` (height /
+/// width), around the focal point or the most detailed part of the image. The copy is kept with
+/// the thumbnails, so it is only computed once. Falls back to the original image if the image
+/// fits without cropping or the copy can't be created.
+pub fn cropped(
+    image: &Path,
+    target_ratio: f32,
+    focus: Option<[f32; 2]>,
+    config: &NerdcliConfig,
+) -> PathBuf {
+    let limit_mb = config.thumbnail_cache_mb.unwrap_or(DEFAULT_CACHE_MB);
+    match cached_crop(image, target_ratio, focus, limit_mb) {
+        Ok(Some(path)) => path,
+        Ok(None) => image.to_path_buf(),
+        Err(e) => {
+            eprintln!("Could not crop `{}`: {}", image.display(), e);
+            image.to_path_buf()
+        }
+    }
+}
+
+fn cached_crop(
+    image: &Path,
+    target_ratio: f32,
+    focus: Option<[f32; 2]>,
+    limit_mb: u64,
+) -> Result<Option<PathBuf>, String> {
+    let (width, height) = dimensions(image).map_err(|e| e.to_string())?;
+    let Some(ratio) = crop_ratio(height as f32 / width as f32, target_ratio) else {
+        return Ok(None);
+    };
+
+    // the ratio is rounded, so small changes of the terminal size use the same copy
+    let ratio = (ratio * 20.0).round() / 20.0;
+    let key = format!("{}|crop|{}|{:?}", file_key(image)?, ratio, focus);
+    let dir = thumbnail_dir();
+    let path = match limit_mb {
+        // without a cache, the copy is overwritten every time
+        0 => uncached_path("crop"),
+        _ => dir.join(format!("{}.png", fnv_hash(key.as_bytes()))),
+    };
+    if limit_mb > 0 && is_cached(&path) {
+        return Ok(Some(path));
+    }
+
+    let img = decode(image)?;
+    let region = region(&img, ratio, focus);
+    let cropped = img.crop_imm(region.x, region.y, region.width, region.height);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let tmp = temp_path(&path);
+    cropped
+        .save_with_format(&tmp, ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    fs::rename(&tmp, &path).map_err(|e| e.to_string())?;
+
+    if limit_mb > 0 {
+        shrink_cache(&dir, limit_mb * 1024 * 1024, &path);
+    }
+    Ok(Some(path))
+}
+
+/// Returns the path of a copy of the image with the effects applied (dithering only for the
+/// `blocks` protocol). The copy is kept with the thumbnails, so it is only computed once.
+/// Falls back to the image without effects if the copy can't be created.
+pub fn with_effects(
+    image: &Path,
+    effects: &[ImageEffect],
+    color: &Color,
+    protocol: &str,
+    config: &NerdcliConfig,
+) -> PathBuf {
+    let effects: Vec<ImageEffect> = effects
+        .iter()
+        .copied()
+        .filter(|e| e.applies_to(protocol))
+        .collect();
+    if effects.is_empty() {
+        return image.to_path_buf();
+    }
+
+    let limit_mb = config.thumbnail_cache_mb.unwrap_or(DEFAULT_CACHE_MB);
+    match cached_effects(image, &effects, color, limit_mb) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!(
+                "Could not apply the image effects to `{}`: {}",
+                image.display(),
+                e
+            );
+            image.to_path_buf()
+        }
+    }
+}
+
+fn cached_effects(
+    image: &Path,
+    effects: &[ImageEffect],
+    color: &Color,
+    limit_mb: u64,
+) -> Result<PathBuf, String> {
+    let key = format!(
+        "{}|effects|{:?}|{},{},{}",
+        file_key(image)?,
+        effects,
+        color.r,
+        color.g,
+        color.b
+    );
+    let dir = thumbnail_dir();
+    let path = match limit_mb {
+        0 => uncached_path("effects"),
+        _ => dir.join(format!("{}.png", fnv_hash(key.as_bytes()))),
+    };
+    if limit_mb > 0 && is_cached(&path) {
+        return Ok(path);
+    }
+
+    let mut img = decode(image)?.to_rgba8();
+    apply(&mut img, effects, color);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let tmp = temp_path(&path);
+    img.save_with_format(&tmp, ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    fs::rename(&tmp, &path).map_err(|e| e.to_string())?;
+
+    if limit_mb > 0 {
+        shrink_cache(&dir, limit_mb * 1024 * 1024, &path);
+    }
+    Ok(path)
+}
+
+fn cell_width_px() -> u32 {
+    match (termion::terminal_size(), termion::terminal_size_pixels()) {
+        (Ok((columns, _)), Ok((width_px, _))) if columns > 0 && width_px > 0 => {
+            (width_px / columns) as u32
+        }
+        _ => DEFAULT_CELL_WIDTH_PX,
+    }
+}
+
+/// Returns path, size and last use of all cached thumbnails.
+fn cached_thumbnails(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let metadata = e.metadata().ok()?;
+            let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            Some((e.path(), metadata.len(), used))
+        })
+        .collect()
+}
+
+/// Deletes the least recently used thumbnails until the cache fits into the limit. `keep` is the
+/// file that was just written and is about to be shown, so it is kept even if it alone is larger
+/// than the limit.
+fn shrink_cache(dir: &Path, limit_bytes: u64, keep: &Path) {
+    let mut thumbnails = cached_thumbnails(dir);
+    let mut size: u64 = thumbnails.iter().map(|(_, size, _)| size).sum();
+    thumbnails.sort_by_key(|(_, _, used)| *used);
+
+    for (path, file_size, _) in thumbnails {
+        if size <= limit_bytes {
+            break;
+        }
+        if path == keep {
+            continue;
+        }
+        if fs::remove_file(&path).is_ok() {
+            size -= file_size;
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    process::{exit, Command},
+};
+
+use clap::{Subcommand, ValueEnum};
+use dialoguer::Confirm;
+use toml::{Table, Value};
+use toml_edit::{DocumentMut, InlineTable, Item, TableLike};
+
+use crate::migrations::migrate_config_file;
+use crate::schema::schema_string;
+use crate::settings::{
+    config_file_path, effective_table, parse_config, parse_setting, validate_config,
+};
+use crate::Cli;
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    #[command(
+        about = "Print the effective configuration (config file, profile, environment and arguments merged)"
+    )]
+    Show {
+        #[arg(short, long, value_enum, default_value_t = Format::Toml)]
+        format: Format,
+    },
+
+    #[command(about = "Print the effective value of a setting, e.g. `layout` or `quote_color.r`")]
+    Get { key: String },
+
+    #[command(about = "Change a setting in the config file (comments are preserved)")]
+    Set { key: String, value: String },
+
+    #[command(about = "Open the config file in $EDITOR and validate it afterwards")]
+    Edit,
+
+    #[command(about = "Print the path of the config file")]
+    Path,
+
+    #[command(
+        about = "Print the JSON schema of the config file (for editor validation and autocompletion)"
+    )]
+    Schema,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    Toml,
+    Json,
+}
+
+pub fn run(command: &ConfigCommand, cli: &Cli) {
+    match command {
+        ConfigCommand::Show { format } => show(*format, cli),
+        ConfigCommand::Get { key } => get(key, cli),
+        ConfigCommand::Set { key, value } => set(key, value),
+        ConfigCommand::Edit => edit(),
+        ConfigCommand::Path => println!("{}", config_file_path().display()),
+        ConfigCommand::Schema => println!("{}", schema_string()),
+    }
+}
+
+fn show(format: Format, cli: &Cli) {
+    let config = parse_config(cli);
+    let output = match format {
+        Format::Toml => toml::to_string_pretty(&config).map_err(|e| e.to_string()),
+        Format::Json => serde_json::to_string_pretty(&config).map_err(|e| e.to_string()),
+    };
+    let output = match output {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("Unable to print the configuration: {}", e);
+            exit(1);
+        }
+    };
+    if let Err(e) = writeln!(io::stdout().lock(), "{}", output.trim_end()) {
+        // e.g. `nerdcli config show | head`
+        if e.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("Unable to print the configuration: {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn get(key: &str, cli: &Cli) {
+    let (table, _, _) = effective_table(cli);
+
+    let mut current = Value::Table(table);
+    for part in key.split('.') {
+        current = match current.get(part) {
+            Some(v) => v.clone(),
+            None => {
+                eprintln!("`{}` is not set", key);
+                exit(1);
+            }
+        };
+    }
+
+    match current {
+        Value::String(s) => println!("{}", s),
+        Value::Table(t) => print!("{}", toml::to_string_pretty(&t).unwrap_or_default()),
+        v => println!("{}", v),
+    }
+}
+
+fn set(key: &str, raw: &str) {
+    let path = config_file_path();
+    let contents = read_config_file();
+    let mut doc = match contents.parse::<DocumentMut>() {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("Unable to parse config file `{}`: {}", path.display(), e);
+            exit(1);
+        }
+    };
+
+    let name = key.rsplit('.').next().unwrap_or(key);
+    let value = match parse_setting(name, raw).and_then(|v| to_edit_value(&v)) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Invalid value for `{}`: {}", key, e);
+            exit(1);
+        }
+    };
+
+    if let Err(e) = set_value(&mut doc, key, value) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+
+    let updated = doc.to_string();
+    if let Err(e) = validate_config(&updated) {
+        eprintln!("The config would be invalid, nothing was changed: {}", e);
+        exit(1);
+    }
+    write_config_file(&updated);
+}
+
+/// Sets the value of a dotted key like `quote_color.r`. Missing tables are created, inline tables
+/// (`quote_color = { r = 1, g = 2, b = 3 }`) are kept inline.
+fn set_value(doc: &mut DocumentMut, key: &str, value: toml_edit::Value) -> Result<(), String> {
+    let parts: Vec<&str> = key.split('.').collect();
+    let (name, parents) = parts.split_last().unwrap();
+
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    let mut inline = false;
+    for parent in parents {
+        let new_table = match inline {
+            true => Item::Value(toml_edit::Value::InlineTable(InlineTable::new())),
+            false => toml_edit::table(),
+        };
+        let item = table.entry(parent).or_insert(new_table);
+        inline = item.is_inline_table();
+        table = item
+            .as_table_like_mut()
+            .ok_or(format!("`{}` is not a table in the config file", parent))?;
+    }
+
+    // the key stays in place, so the comments above it are kept
+    match table.get_mut(name) {
+        Some(Item::Value(old)) => {
+            let mut value = value;
+            // keep the comments and spacing around the old value
+            *value.decor_mut() = old.decor().clone();
+            *old = value;
+        }
+        Some(item) => *item = Item::Value(value),
+        None => {
+            table.insert(name, Item::Value(value));
+        }
+    }
+    Ok(())
+}
+
+fn to_edit_value(value: &Value) -> Result<toml_edit::Value, String> {
+    let mut table = Table::new();
+    table.insert("value".to_string(), value.clone());
+    let doc = toml::to_string(&table)
+        .map_err(|e| e.to_string())?
+        .parse::<DocumentMut>()
+        .map_err(|e| e.to_string())?;
+
+    match doc.get("value") {
+        Some(Item::Value(v)) => Ok(v.clone()),
+        Some(Item::Table(t)) => Ok(toml_edit::Value::InlineTable(t.clone().into_inline_table())),
+        _ => Err("unsupported value".to_string()),
+    }
+}
+
+fn edit() {
+    let path = config_file_path();
+    let original = read_config_file();
+    let editor = env::var("VISUAL")
+        .or(env::var("EDITOR"))
+        .unwrap_or(if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+
+    loop {
+        // the editor may come with arguments, e.g. `code --wait`
+        let mut args = editor.split_whitespace();
+        let program = args.next().unwrap_or("vi");
+        match Command::new(program).args(args).arg(&path).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => {
+                eprintln!("`{}` exited with {}", editor, status);
+                exit(1);
+            }
+            Err(e) => {
+                eprintln!("Could not start the editor `{}`: {}", editor, e);
+                exit(1);
+            }
+        }
+
+        match validate_config(&read_config_file()) {
+            Ok(_) => {
+                println!("The config file is valid.");
+                return;
+            }
+            Err(e) => {
+                eprintln!("The config file is invalid: {}", e);
+                let again = Confirm::new()
+                    .with_prompt("Do you want to edit it again?")
+                    .default(true)
+                    .interact()
+                    .unwrap_or(false);
+                if !again {
+                    write_config_file(&original);
+                    eprintln!("The previous version of the config file was restored.");
+                    exit(1);
+                }
+            }
+        }
+    }
+}
+
+fn read_config_file() -> String {
+    let path = config_file_path();
+    migrate_config_file(&path);
+    match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!(
+                "Could not read config file from `{}`: Error: {}",
+                path.display(),
+                e
+            );
+            exit(1);
+        }
+    }
+}
+
+fn write_config_file(contents: &str) {
+    let path = config_file_path();
+    if let Err(e) = fs::write(&path, contents) {
+        eprintln!("Could not write config file `{}`: {}", path.display(), e);
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(contents: &str, key: &str, value: i64) -> String {
+        let mut doc = contents.parse::<DocumentMut>().unwrap();
+        set_value(&mut doc, key, value.into()).unwrap();
+        doc.to_string()
+    }
+
+    #[test]
+    fn sets_a_key_in_an_inline_table() {
+        assert_eq!(
+            set(
+                "quote_color = { r = 1, g = 2, b = 3 }\n",
+                "quote_color.r",
+                10
+            ),
+            "quote_color = { r = 10, g = 2, b = 3 }\n"
+        );
+    }
+
+    #[test]
+    fn sets_a_key_in_a_table() {
+        assert_eq!(
+            set("[quote_color]\nr = 1 # red\n", "quote_color.r", 10),
+            "[quote_color]\nr = 10 # red\n"
+        );
+    }
+
+    #[test]
+    fn keeps_the_comments_above_the_key() {
+        let contents =
+            "# preferred layout\n# ROW: the quote next to the image\nlayout = \"ROW\" # or COL\n";
+        let mut doc = contents.parse::<DocumentMut>().unwrap();
+        set_value(&mut doc, "layout", "COL".into()).unwrap();
+        assert_eq!(
+            doc.to_string(),
+            "# preferred layout\n# ROW: the quote next to the image\nlayout = \"COL\" # or COL\n"
+        );
+    }
+
+    #[test]
+    fn keeps_the_spacing_of_inline_tables() {
+        assert_eq!(
+            set("quote_color = {r=1, g = 2,b = 3}\n", "quote_color.b", 30),
+            "quote_color = {r=1, g = 2,b = 30}\n"
+        );
+    }
+
+    #[test]
+    fn creates_missing_tables() {
+        assert_eq!(
+            set("layout = \"ROW\"\n", "image_weights.comics", 2),
+            "layout = \"ROW\"\n\n[image_weights]\ncomics = 2\n"
+        );
+    }
+
+    #[test]
+    fn rejects_keys_below_values() {
+        let mut doc = "layout = \"ROW\"\n".parse::<DocumentMut>().unwrap();
+        assert!(set_value(&mut doc, "layout.x", 1.into()).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use std::{collections::BTreeSet, fs, path::Path};
+
+use serde_derive::Deserialize;
+
+use crate::effects::ImageEffect;
+use crate::quotes::Quote;
+use crate::selection::relative_path;
+use crate::tags::{image_tags, TAGS_FILE};
+
+/// The optional file next to an image with the same name and the extension `.toml`,
+/// e.g. `hellboy-01.toml` for `hellboy-01.png`.
+#[derive(Deserialize, Default)]
+pub struct ImageSidecar {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Quotes that are always shown with the image.
+    #[serde(default)]
+    pub quotes: Vec<Quote>,
+    /// The ids of quotes in the quotes_dir that are always shown with the image.
+    #[serde(default)]
+    pub quote_ids: Vec<String>,
+    /// The point to keep in view when the image is cropped (crop_to_fit), relative to its size,
+    /// e.g. `[0.5, 0.3]` for the middle of the upper third.
+    pub focus: Option<[f32; 2]>,
+    /// Replaces the image_effects of the config for this image, e.g. `[]` to show it unchanged.
+    pub effects: Option<Vec<ImageEffect>>,
+}
+
+impl ImageSidecar {
+    pub fn load(image: &Path) -> ImageSidecar {
+        let path = image.with_extension("toml");
+        // the tags.toml in the image_dir belongs to all images, not to an image named tags.*
+        if path.file_name().and_then(|n| n.to_str()) == Some(TAGS_FILE) {
+            return ImageSidecar::default();
+        }
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return ImageSidecar::default();
+        };
+        let mut sidecar: ImageSidecar = toml::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("Ignoring `{}`: {}", path.display(), e);
+            ImageSidecar::default()
+        });
+        for quote in sidecar.quotes.iter_mut() {
+            quote.file = Some(path.clone());
+        }
+        sidecar
+    }
+}
+
+/// The selected image with everything that belongs to it.
+pub struct ImageAsset {
+    /// The path relative to the image_dir, separated by `/` (None for images outside of it).
+    pub relative: Option<String>,
+    pub sidecar: ImageSidecar,
+    pub tags: BTreeSet<String>,
+}
+
+impl ImageAsset {
+    pub fn load(root: &Path, image: &Path) -> ImageAsset {
+        let sidecar = ImageSidecar::load(image);
+        ImageAsset {
+            relative: image.starts_with(root).then(|| relative_path(root, image)),
+            tags: image_tags(root, image, &sidecar),
+            sidecar,
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+
+/// The part of an image that must stay visible along the cropped side, so a cropped image is
+/// still recognizable.
+const MIN_VISIBLE: f32 = 0.5;
+
+/// Images whose aspect ratio differs less than this from the space for the image are not cropped.
+const TOLERANCE: f32 = 0.1;
+
+/// The size of the downscaled copy that the saliency is estimated on.
+const SALIENCY_SIZE: u32 = 64;
+
+/// A rectangle of an image in pixels.
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The aspect ratio (height / width) to crop an image with the ratio `image_ratio` to, so it fills
+/// a space with the ratio `target_ratio`. None, if the image already fits well enough.
+pub fn crop_ratio(image_ratio: f32, target_ratio: f32) -> Option<f32> {
+    if image_ratio <= 0.0 || target_ratio <= 0.0 {
+        return None;
+    }
+    if (image_ratio / target_ratio - 1.0).abs() < TOLERANCE {
+        return None;
+    }
+    // a wide image gets narrower (its ratio grows), a tall one gets lower
+    let ratio = if image_ratio < target_ratio {
+        target_ratio.min(image_ratio / MIN_VISIBLE)
+    } else {
+        target_ratio.max(image_ratio * MIN_VISIBLE)
+    };
+    Some(ratio)
+}
+
+/// The region with the aspect ratio `ratio` (height / width) around the focal point (relative to
+/// the size of the image, e.g. `[0.5, 0.3]`), or around the most detailed part of the image.
+pub fn region(img: &DynamicImage, ratio: f32, focus: Option<[f32; 2]>) -> Region {
+    let (width, height) = img.dimensions();
+    let (crop_width, crop_height) = if (height as f32 / width as f32) < ratio {
+        ((height as f32 / ratio).round() as u32, height)
+    } else {
+        (width, (width as f32 * ratio).round() as u32)
+    };
+    let (crop_width, crop_height) = (crop_width.clamp(1, width), crop_height.clamp(1, height));
+
+    let (x, y) = match focus {
+        Some([fx, fy]) => (
+            centered(fx * width as f32, crop_width, width),
+            centered(fy * height as f32, crop_height, height),
+        ),
+        None => salient_offset(img, crop_width, crop_height),
+    };
+    Region {
+        x,
+        y,
+        width: crop_width,
+        height: crop_height,
+    }
+}
+
+/// The offset of a window of `size` centered on `center`, kept inside `0..total`.
+fn centered(center: f32, size: u32, total: u32) -> u32 {
+    (center - size as f32 / 2.0)
+        .round()
+        .clamp(0.0, (total - size) as f32) as u32
+}
+
+/// Finds the window with the highest entropy of the brightness, i.e. with the most detail, instead
+/// of sky or a plain background. Only the cropped side is moved.
+fn salient_offset(img: &DynamicImage, crop_width: u32, crop_height: u32) -> (u32, u32) {
+    let (width, height) = img.dimensions();
+    let small = img
+        .resize(SALIENCY_SIZE, SALIENCY_SIZE, FilterType::Triangle)
+        .to_luma8();
+    let (small_width, small_height) = small.dimensions();
+    let scale = small_width as f32 / width as f32;
+    let window_width = ((crop_width as f32 * scale).round() as u32).clamp(1, small_width);
+    let window_height = ((crop_height as f32 * scale).round() as u32).clamp(1, small_height);
+
+    let entropy = |x: u32, y: u32| {
+        let mut histogram = [0u32; 32];
+        for wy in y..y + window_height {
+            for wx in x..x + window_width {
+                histogram[small.get_pixel(wx, wy).0[0] as usize / 8] += 1;
+            }
+        }
+        let total = (window_width * window_height) as f32;
+        histogram
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f32 / total;
+                -p * p.log2()
+            })
+            .sum::<f32>()
+    };
+    let best = |positions: u32, at: &dyn Fn(u32) -> f32| {
+        (0..=positions)
+            .map(|p| (p, at(p)))
+            .fold((0, f32::MIN), |best, current| {
+                if current.1 > best.1 {
+                    current
+                } else {
+                    best
+                }
+            })
+            .0
+    };
+
+    if crop_width < width {
+        let x = best(small_width - window_width, &|x| entropy(x, 0));
+        (
+            ((x as f32 / scale).round() as u32).min(width - crop_width),
+            0,
+        )
+    } else {
+        let y = best(small_height - window_height, &|y| entropy(0, y));
+        (
+            0,
+            ((y as f32 / scale).round() as u32).min(height - crop_height),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    /// A plain image with a pattern of gray blocks where `detailed` is true.
+    fn image(width: u32, height: u32, detailed: impl Fn(u32, u32) -> bool) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+            match detailed(x, y) {
+                true => Luma([((x / 20 * 3 + y / 20 * 7) * 23 % 256) as u8]),
+                false => Luma([200]),
+            }
+        }))
+    }
+
+    #[test]
+    fn does_not_crop_images_that_nearly_fit() {
+        assert_eq!(crop_ratio(1.0, 1.05), None);
+        assert_eq!(crop_ratio(0.0, 1.0), None);
+    }
+
+    #[test]
+    fn keeps_at_least_half_of_the_cropped_side() {
+        assert_eq!(crop_ratio(0.8, 1.0), Some(1.0));
+        assert_eq!(crop_ratio(0.25, 1.0), Some(0.5));
+        assert_eq!(crop_ratio(4.0, 1.0), Some(2.0));
+    }
+
+    #[test]
+    fn crops_around_the_focus() {
+        let img = image(200, 100, |_, _| false);
+        let left = region(&img, 1.0, Some([0.0, 0.5]));
+        assert_eq!((left.x, left.y, left.width, left.height), (0, 0, 100, 100));
+        let right = region(&img, 1.0, Some([0.9, 0.5]));
+        assert_eq!(right.x, 100);
+        let middle = region(&img, 1.0, Some([0.5, 0.5]));
+        assert_eq!(middle.x, 50);
+    }
+
+    #[test]
+    fn crops_to_the_most_detailed_part() {
+        let wide = image(200, 100, |x, _| x >= 120);
+        let (x, y) = salient_offset(&wide, 100, 100);
+        assert!(x >= 90, "x = {}", x);
+        assert_eq!(y, 0);
+
+        let tall = image(100, 300, |_, y| y < 100);
+        let (x, y) = salient_offset(&tall, 100, 100);
+        assert_eq!(x, 0);
+        assert!(y <= 10, "y = {}", y);
+    }
+}
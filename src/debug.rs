@@ -0,0 +1,134 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    time::Instant,
+};
+
+use clap::ValueEnum;
+use serde_derive::Serialize;
+
+use crate::effects::ImageEffect;
+use crate::quotes::Quote;
+use crate::settings::NerdcliConfig;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum DebugFormat {
+    Text,
+    Json,
+}
+
+/// Measures the duration of the single steps of a run.
+pub struct Timings {
+    start: Instant,
+    last: Instant,
+    steps: BTreeMap<String, f64>,
+}
+
+impl Timings {
+    pub fn start() -> Timings {
+        let now = Instant::now();
+        Timings {
+            start: now,
+            last: now,
+            steps: BTreeMap::new(),
+        }
+    }
+
+    /// Records the time since the last call (or the start) under the given name.
+    pub fn lap(&mut self, name: &str) {
+        let now = Instant::now();
+        self.steps.insert(
+            name.to_string(),
+            now.duration_since(self.last).as_secs_f64() * 1000.0,
+        );
+        self.last = now;
+    }
+
+    fn total(&self) -> f64 {
+        self.last.duration_since(self.start).as_secs_f64() * 1000.0
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize)]
+pub struct TerminalInfo {
+    pub columns: u16,
+    pub rows: u16,
+    pub width_px: Option<u16>,
+    pub height_px: Option<u16>,
+}
+
+impl TerminalInfo {
+    pub fn detect() -> TerminalInfo {
+        let (columns, rows) = termion::terminal_size().unwrap_or((0, 0));
+        let pixels = termion::terminal_size_pixels().ok();
+        TerminalInfo {
+            columns,
+            rows,
+            width_px: pixels.map(|(w, _)| w),
+            height_px: pixels.map(|(_, h)| h),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ImageInfo {
+    pub path: String,
+    pub width_px: Option<u32>,
+    pub height_px: Option<u32>,
+    pub tags: BTreeSet<String>,
+    /// The cropped copy that was shown instead of the image (crop_to_fit).
+    pub cropped: Option<String>,
+    /// The image_effects of the config, or of the sidecar file of the image.
+    pub effects: Vec<ImageEffect>,
+}
+
+#[derive(Serialize)]
+pub struct QuoteInfo {
+    #[serde(flatten)]
+    pub quote: Quote,
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+pub struct LayoutInfo {
+    pub layout: String,
+    pub image: Rect,
+    pub quote: Rect,
+}
+
+/// Everything that is needed to reproduce a run, printed with `--debug=json`.
+#[derive(Serialize)]
+pub struct DebugReport {
+    pub config: NerdcliConfig,
+    pub origins: BTreeMap<String, String>,
+    pub profile: Option<String>,
+    /// Run again with `--seed` to select the same image and quote.
+    pub seed: u64,
+    pub terminal: TerminalInfo,
+    pub protocol: String,
+    pub image: Option<ImageInfo>,
+    pub images_found: usize,
+    pub quote: QuoteInfo,
+    pub layout: LayoutInfo,
+    pub timings_ms: BTreeMap<String, f64>,
+}
+
+impl DebugReport {
+    pub fn print_json(mut self, timings: &Timings) {
+        self.timings_ms = timings.steps.clone();
+        self.timings_ms.insert("total".to_string(), timings.total());
+        match serde_json::to_string_pretty(&self) {
+            // stderr, so the document can be separated from the rendered greeting
+            Ok(json) => eprintln!("{}", json),
+            Err(e) => eprintln!("Unable to serialize the debug information: {}", e),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use directories::ProjectDirs;
 
+use crate::schema::write_schema_file;
 use dialoguer::Confirm;
 use fs_extra::{copy_items, dir};
 use std::fs::create_dir_all;
@@ -22,7 +23,8 @@ pub fn ask_for_config_creation(proj_dirs: ProjectDirs) {
     if confirmation {
         // create base config path
         if create_dir(proj_dirs.config_dir()) {
-            copy_file(Path::new("./assets/nerdcli.toml"), proj_dirs.config_dir())
+            copy_file(Path::new("./assets/nerdcli.toml"), proj_dirs.config_dir());
+            write_schema_file(proj_dirs.config_dir());
         }
         // create quotes path
         let quote_path = proj_dirs.config_dir().join("quotes/en");
@@ -75,12 +77,10 @@ fn copy_file(source: &Path, target: &Path) {
 
 fn create_dir(path: &Path) -> bool {
     match create_dir_all(path) {
-        Ok(_) => {
-            return true;
-        }
+        Ok(_) => true,
         Err(e) => {
             eprint!("Error: Could not create config directories: {}", e);
-            return false;
+            false
         }
-    };
+    }
 }
@@ -0,0 +1,266 @@
+use std::fmt;
+
+use image::RgbaImage;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::quotes::Color;
+
+/// The levels of each color channel in the 256 color palette of terminals (the 6×6×6 color cube).
+const PALETTE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The average distance of the palette levels, i.e. how far ordered dithering moves a channel.
+const PALETTE_STEP: f32 = 51.0;
+
+/// The 4×4 Bayer matrix of the ordered dithering.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// How far `tint` moves the colors toward the effect color.
+const TINT_STRENGTH: f32 = 0.5;
+
+/// The radius of rounded corners, relative to the shorter side of the image.
+const CORNER_RADIUS: f32 = 0.1;
+
+/// The width of the border, relative to the shorter side of the image.
+const BORDER_WIDTH: f32 = 0.02;
+
+/// Filters applied to an image before it is shown, in the order of the list.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageEffect {
+    Grayscale,
+    Sepia,
+    /// Moves the colors toward the effect_color, keeping the brightness.
+    Tint,
+    /// Floyd–Steinberg dithering to the 256 color palette.
+    Dither,
+    /// Ordered (Bayer) dithering to the 256 color palette.
+    OrderedDither,
+    RoundedCorners,
+    /// A frame in the effect_color, following rounded corners applied before it.
+    Border,
+}
+
+impl ImageEffect {
+    /// Dithering is only useful for images drawn with block characters; graphics protocols show
+    /// all colors anyway.
+    pub fn applies_to(self, protocol: &str) -> bool {
+        !matches!(self, ImageEffect::Dither | ImageEffect::OrderedDither) || protocol == "blocks"
+    }
+}
+
+impl fmt::Display for ImageEffect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ImageEffect::Grayscale => "grayscale",
+            ImageEffect::Sepia => "sepia",
+            ImageEffect::Tint => "tint",
+            ImageEffect::Dither => "dither",
+            ImageEffect::OrderedDither => "ordered_dither",
+            ImageEffect::RoundedCorners => "rounded_corners",
+            ImageEffect::Border => "border",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Applies the effects in the given order. `color` is used by tint and border.
+pub fn apply(img: &mut RgbaImage, effects: &[ImageEffect], color: &Color) {
+    let shorter = img.width().min(img.height()) as f32;
+    let color = [color.r as f32, color.g as f32, color.b as f32];
+    // the border follows the corners, if they were rounded before
+    let mut radius = 0.0;
+    for effect in effects {
+        match effect {
+            ImageEffect::Grayscale => map_colors(img, |rgb| [luma(rgb); 3]),
+            ImageEffect::Sepia => map_colors(img, |[r, g, b]| {
+                [
+                    0.393 * r + 0.769 * g + 0.189 * b,
+                    0.349 * r + 0.686 * g + 0.168 * b,
+                    0.272 * r + 0.534 * g + 0.131 * b,
+                ]
+            }),
+            ImageEffect::Tint => map_colors(img, |rgb| {
+                let brightness = luma(rgb) / 255.0;
+                [0, 1, 2].map(|c| rgb[c] + (color[c] * brightness - rgb[c]) * TINT_STRENGTH)
+            }),
+            ImageEffect::Dither => floyd_steinberg(img),
+            ImageEffect::OrderedDither => ordered_dither(img),
+            ImageEffect::RoundedCorners => {
+                radius = (shorter * CORNER_RADIUS).round();
+                round_corners(img, radius);
+            }
+            ImageEffect::Border => {
+                let width = (shorter * BORDER_WIDTH).round().max(1.0);
+                border(img, width, radius, color);
+            }
+        }
+    }
+}
+
+fn luma([r, g, b]: [f32; 3]) -> f32 {
+    0.299 * r + 0.587 * g + 0.114 * b
+}
+
+/// Replaces the color of every pixel, keeping its transparency.
+fn map_colors(img: &mut RgbaImage, f: impl Fn([f32; 3]) -> [f32; 3]) {
+    for pixel in img.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        let [r, g, b] =
+            f([r as f32, g as f32, b as f32]).map(|c| c.round().clamp(0.0, 255.0) as u8);
+        pixel.0 = [r, g, b, a];
+    }
+}
+
+fn nearest_level(value: f32) -> u8 {
+    PALETTE_LEVELS
+        .iter()
+        .copied()
+        .min_by(|a, b| {
+            (*a as f32 - value)
+                .abs()
+                .total_cmp(&(*b as f32 - value).abs())
+        })
+        .unwrap_or(0)
+}
+
+/// Reduces every channel to the palette levels and spreads the error to the neighboring pixels.
+fn floyd_steinberg(img: &mut RgbaImage) {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let mut colors: Vec<[f32; 3]> = img
+        .pixels()
+        .map(|p| [p.0[0] as f32, p.0[1] as f32, p.0[2] as f32])
+        .collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let old = colors[y * width + x];
+            let new = old.map(nearest_level);
+            for c in 0..3 {
+                let error = old[c] - new[c] as f32;
+                let mut spread = |dx: isize, dy: usize, share: f32| {
+                    let nx = x as isize + dx;
+                    if nx >= 0 && (nx as usize) < width && y + dy < height {
+                        colors[(y + dy) * width + nx as usize][c] += error * share;
+                    }
+                };
+                spread(1, 0, 7.0 / 16.0);
+                spread(-1, 1, 3.0 / 16.0);
+                spread(0, 1, 5.0 / 16.0);
+                spread(1, 1, 1.0 / 16.0);
+            }
+            let pixel = img.get_pixel_mut(x as u32, y as u32);
+            pixel.0 = [new[0], new[1], new[2], pixel.0[3]];
+        }
+    }
+}
+
+/// Reduces every channel to the palette levels after adding the threshold of the Bayer matrix.
+fn ordered_dither(img: &mut RgbaImage) {
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let threshold = (BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.0 - 0.5;
+        for c in 0..3 {
+            pixel.0[c] = nearest_level(pixel.0[c] as f32 + threshold * PALETTE_STEP);
+        }
+    }
+}
+
+/// The distance of the center of a pixel to the edge of the image with corners of `radius`,
+/// negative inside the image.
+fn edge_distance(img: &RgbaImage, x: u32, y: u32, radius: f32) -> f32 {
+    let (half_width, half_height) = (img.width() as f32 / 2.0, img.height() as f32 / 2.0);
+    let dx = (x as f32 + 0.5 - half_width).abs() - (half_width - radius);
+    let dy = (y as f32 + 0.5 - half_height).abs() - (half_height - radius);
+    dx.max(0.0).hypot(dy.max(0.0)) + dx.max(dy).min(0.0) - radius
+}
+
+/// Makes the pixels outside the rounded corners transparent, with a smooth edge.
+fn round_corners(img: &mut RgbaImage, radius: f32) {
+    for y in 0..img.height() {
+        for x in 0..img.width() {
+            let coverage = (0.5 - edge_distance(img, x, y, radius)).clamp(0.0, 1.0);
+            let pixel = img.get_pixel_mut(x, y);
+            pixel.0[3] = (pixel.0[3] as f32 * coverage).round() as u8;
+        }
+    }
+}
+
+fn border(img: &mut RgbaImage, width: f32, radius: f32, color: [f32; 3]) {
+    let [r, g, b] = color.map(|c| c as u8);
+    for y in 0..img.height() {
+        for x in 0..img.width() {
+            if edge_distance(img, x, y, radius) > -width {
+                let pixel = img.get_pixel_mut(x, y);
+                pixel.0 = [r, g, b, pixel.0[3]];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const RED: Color = Color { r: 255, g: 0, b: 0 };
+
+    fn plain(size: u32, pixel: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(size, size, Rgba(pixel))
+    }
+
+    #[test]
+    fn rounds_to_the_nearest_palette_level() {
+        assert_eq!(nearest_level(-20.0), 0);
+        assert_eq!(nearest_level(100.0), 95);
+        assert_eq!(nearest_level(160.0), 175);
+        assert_eq!(nearest_level(300.0), 255);
+    }
+
+    #[test]
+    fn grayscale_keeps_the_transparency() {
+        let mut img = plain(2, [255, 0, 0, 200]);
+        apply(&mut img, &[ImageEffect::Grayscale], &RED);
+        assert_eq!(img.get_pixel(1, 1).0, [76, 76, 76, 200]);
+    }
+
+    #[test]
+    fn dithering_only_uses_palette_colors() {
+        for effect in [ImageEffect::Dither, ImageEffect::OrderedDither] {
+            let mut img = plain(8, [100, 100, 100, 255]);
+            apply(&mut img, &[effect], &RED);
+            assert!(img
+                .pixels()
+                .all(|p| p.0[..3].iter().all(|c| PALETTE_LEVELS.contains(c))));
+            // the average brightness is kept
+            let average = img.pixels().map(|p| p.0[0] as f32).sum::<f32>() / 64.0;
+            assert!((average - 100.0).abs() < 15.0, "{}: {}", effect, average);
+        }
+        assert!(!ImageEffect::Dither.applies_to("kitty"));
+        assert!(ImageEffect::Dither.applies_to("blocks"));
+    }
+
+    #[test]
+    fn measures_the_distance_to_the_rounded_edge() {
+        let img = plain(100, [0; 4]);
+        assert_eq!(edge_distance(&img, 0, 50, 0.0), -0.5);
+        assert_eq!(edge_distance(&img, 50, 50, 10.0), -49.5);
+        assert!(edge_distance(&img, 0, 0, 10.0) > 0.0);
+    }
+
+    #[test]
+    fn rounds_the_corners() {
+        let mut img = plain(100, [0, 0, 255, 255]);
+        apply(&mut img, &[ImageEffect::RoundedCorners], &RED);
+        assert_eq!(img.get_pixel(0, 0).0[3], 0);
+        assert_eq!(img.get_pixel(0, 50).0[3], 255);
+        assert_eq!(img.get_pixel(50, 50).0[3], 255);
+    }
+
+    #[test]
+    fn draws_the_border_in_the_effect_color() {
+        let mut img = plain(100, [0, 0, 255, 255]);
+        apply(&mut img, &[ImageEffect::Border], &RED);
+        assert_eq!(img.get_pixel(0, 50).0, [255, 0, 0, 255]);
+        assert_eq!(img.get_pixel(1, 50).0, [255, 0, 0, 255]);
+        assert_eq!(img.get_pixel(2, 50).0, [0, 0, 255, 255]);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    fs::{self, metadata},
+    io,
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Selects files by glob patterns that are matched against the path relative to the content
+/// directory, e.g. `comics/**` or `**/*.png`. Patterns starting with `!` exclude files,
+/// e.g. `!**/nsfw/**`. Without any including pattern, all files that are not excluded are selected.
+/// The image_filter and the quote_filter are both read into a PathFilter, so they work the same.
+pub struct PathFilter {
+    include: GlobSet,
+    exclude: GlobSet,
+    /// The excluding patterns that end with `/**` without it, i.e. the folders that are excluded
+    /// completely and don't need to be read.
+    excluded_dirs: GlobSet,
+}
+
+impl PathFilter {
+    pub fn new(patterns: &[String]) -> Result<PathFilter, String> {
+        let mut include = GlobSetBuilder::new();
+        let mut exclude = GlobSetBuilder::new();
+        let mut excluded_dirs = GlobSetBuilder::new();
+
+        for pattern in patterns {
+            if let Some(dir) = pattern
+                .strip_prefix('!')
+                .and_then(|p| p.strip_suffix("/**"))
+            {
+                excluded_dirs.add(glob(dir, pattern)?);
+            }
+            let (builder, glob) = match pattern.strip_prefix('!') {
+                Some(p) => (&mut exclude, p),
+                None => (&mut include, pattern.as_str()),
+            };
+            builder.add(self::glob(glob, pattern)?);
+        }
+
+        Ok(PathFilter {
+            include: include.build().map_err(|e| e.to_string())?,
+            exclude: exclude.build().map_err(|e| e.to_string())?,
+            excluded_dirs: excluded_dirs.build().map_err(|e| e.to_string())?,
+        })
+    }
+
+    pub fn is_match(&self, relative_path: &Path) -> bool {
+        let path = slash_path(relative_path);
+        (self.include.is_empty() || self.include.is_match(&path)) && !self.exclude.is_match(&path)
+    }
+
+    /// True, if no file in the folder can be selected, e.g. `nsfw` for `!nsfw/**`.
+    pub fn excludes_dir(&self, relative_dir: &Path) -> bool {
+        self.excluded_dirs.is_match(slash_path(relative_dir))
+    }
+}
+
+fn glob(glob: &str, pattern: &str) -> Result<Glob, String> {
+    GlobBuilder::new(glob)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| format!("invalid pattern `{}`: {}", pattern, e))
+}
+
+/// Always match with `/`, so the same patterns work on every OS.
+fn slash_path(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn _list_files(
+    vec: &mut Vec<PathBuf>,
+    root: &Path,
+    path: &Path,
+    filter: &PathFilter,
+    accept: &dyn Fn(&Path) -> bool,
+) -> io::Result<()> {
+    if metadata(path)?.is_dir() {
+        let paths = fs::read_dir(path)?;
+        for path_result in paths {
+            let full_path = path_result?.path();
+            let relative = full_path.strip_prefix(root).unwrap_or(&full_path);
+            if metadata(&full_path)?.is_dir() {
+                if !filter.excludes_dir(relative) {
+                    _list_files(vec, root, &full_path, filter, accept)?
+                }
+            } else if accept(&full_path) && filter.is_match(relative) {
+                vec.push(full_path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Lists all files below `root` that are selected by the filter and accepted by `accept`
+/// (e.g. by their file type).
+pub fn list_files(
+    root: &Path,
+    filter: &PathFilter,
+    accept: &dyn Fn(&Path) -> bool,
+) -> io::Result<Vec<PathBuf>> {
+    let mut vec = Vec::new();
+    _list_files(&mut vec, root, root, filter, accept)?;
+    Ok(vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(patterns: &[&str]) -> PathFilter {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        PathFilter::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn selects_everything_without_patterns() {
+        assert!(filter(&[]).is_match(Path::new("comics/hellboy/01.png")));
+    }
+
+    #[test]
+    fn includes_and_excludes() {
+        let f = filter(&["comics/**", "!**/nsfw/**"]);
+        assert!(f.is_match(Path::new("comics/hellboy/01.png")));
+        assert!(!f.is_match(Path::new("comics/nsfw/01.png")));
+        assert!(!f.is_match(Path::new("movies/01.png")));
+    }
+
+    #[test]
+    fn star_stays_within_a_folder() {
+        let f = filter(&["comics/*"]);
+        assert!(f.is_match(Path::new("comics/01.png")));
+        assert!(!f.is_match(Path::new("comics/hellboy/01.png")));
+    }
+
+    #[test]
+    fn only_excludes_folders_that_are_excluded_completely() {
+        let f = filter(&["!**/nsfw/**", "!comics/*.gif"]);
+        assert!(f.excludes_dir(Path::new("nsfw")));
+        assert!(f.excludes_dir(Path::new("comics/nsfw")));
+        assert!(!f.excludes_dir(Path::new("comics")));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(PathFilter::new(&["comics/[".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs, io,
+    path::PathBuf,
+};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::settings::state_dir;
+
+/// An image or a quote that was shown.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Shown {
+    /// The path of an image or the key of a quote.
+    pub key: String,
+    /// Something for humans to recognize it, e.g. the text of a quote.
+    pub label: String,
+}
+
+/// The recently shown images or quotes.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Track {
+    /// The last shown entry, for `nerdcli fav`, `ban` and `rate`.
+    #[serde(default)]
+    pub last: Option<Shown>,
+    /// Newest first, as many as `avoid_repeats`.
+    recent: VecDeque<String>,
+    /// Everything shown in the current cycle of the `shuffle` mode.
+    cycle: BTreeSet<String>,
+}
+
+impl Track {
+    /// True, if the entry must not be selected now.
+    pub fn skip(&self, key: &str, avoid_repeats: usize, shuffle: bool) -> bool {
+        self.recent.iter().take(avoid_repeats).any(|k| k == key)
+            || (shuffle && self.cycle.contains(key))
+    }
+
+    /// Starts a new shuffle cycle, if every candidate was shown in the current one.
+    pub fn start_cycle(&mut self, keys: &[String]) {
+        if keys.iter().all(|k| self.cycle.contains(k)) {
+            self.cycle.clear();
+        }
+    }
+
+    pub fn record(&mut self, key: &str, label: &str, avoid_repeats: usize, shuffle: bool) {
+        self.last = Some(Shown {
+            key: key.to_string(),
+            label: label.to_string(),
+        });
+        self.recent.retain(|k| k != key);
+        self.recent.push_front(key.to_string());
+        self.recent.truncate(avoid_repeats);
+        if shuffle {
+            self.cycle.insert(key.to_string());
+        }
+    }
+}
+
+/// The history of shown images and quotes, kept between runs in the state directory.
+#[derive(Serialize, Deserialize, Default)]
+pub struct History {
+    pub images: Track,
+    pub quotes: Track,
+}
+
+fn history_file() -> PathBuf {
+    state_dir().join("history.json")
+}
+
+impl History {
+    /// Loads the history. A missing or broken file results in an empty history.
+    pub fn load() -> History {
+        fs::read_to_string(history_file())
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default()
+    }
+
+    /// Saves only the last shown image and quote, but keeps the recently shown ones and the
+    /// shuffle cycles, e.g. for a selection from a fixed seed.
+    pub fn save_last(&self) {
+        let mut saved = History::load();
+        saved.images.last = self.images.last.clone();
+        saved.quotes.last = self.quotes.last.clone();
+        saved.save();
+    }
+
+    pub fn save(&self) {
+        let path = history_file();
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| serde_json::to_string(self).map_err(io::Error::other))
+            .and_then(|json| fs::write(&path, json));
+        if let Err(e) = result {
+            eprintln!("Could not write the history `{}`: {}", path.display(), e);
+        }
+    }
+}
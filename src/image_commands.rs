@@ -0,0 +1,222 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf, MAIN_SEPARATOR_STR},
+    process::exit,
+};
+
+use chrono::Local;
+use clap::{Args, Subcommand};
+use image::{imageops::FilterType, DynamicImage, ImageFormat};
+
+use crate::images::is_image;
+use crate::settings::{parse_config, NerdcliConfig};
+use crate::Cli;
+
+/// How much a pixel may differ from the border color (per channel) to be cropped.
+const BORDER_TOLERANCE: u8 = 8;
+
+#[derive(Subcommand)]
+pub enum ImageCommand {
+    #[command(
+        about = "Copy an image into the image_dir, optionally cropped, downscaled and converted to PNG"
+    )]
+    Add(AddArgs),
+}
+
+#[derive(Args)]
+pub struct AddArgs {
+    #[arg(
+        help = "The image file, or - to read it from stdin (e.g. `wl-paste | nerdcli image add - --name hellboy`)"
+    )]
+    source: String,
+
+    #[arg(
+        short,
+        long,
+        help = "The folder in the image_dir to copy the image to, e.g. comics/hellboy"
+    )]
+    folder: Option<String>,
+
+    #[arg(
+        long,
+        help = "The file name (default: the name of the file, or the current time for stdin)"
+    )]
+    name: Option<String>,
+
+    #[arg(
+        long,
+        help = "Crop the borders of a single color, e.g. the black bars of a screenshot"
+    )]
+    crop_borders: bool,
+
+    #[arg(
+        long,
+        help = "Downscale the image, so that its longer side has at most this many pixels"
+    )]
+    max_size: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Convert the image to PNG, which is shown faster than JPG"
+    )]
+    png: bool,
+
+    #[arg(
+        long,
+        help = "Remove metadata like EXIF (done by the other options as well, as they encode the image anew)"
+    )]
+    strip_metadata: bool,
+
+    #[arg(long, help = "Overwrite an existing image with the same name")]
+    force: bool,
+}
+
+pub fn run(command: &ImageCommand, cli: &Cli) {
+    let config = parse_config(cli);
+    match command {
+        ImageCommand::Add(args) => add(args, &config),
+    }
+}
+
+fn add(args: &AddArgs, config: &NerdcliConfig) {
+    let (bytes, source_name) = read_source(&args.source);
+    let Ok(format) = image::guess_format(&bytes) else {
+        eprintln!("`{}` is not an image", args.source);
+        exit(1);
+    };
+
+    let name = args
+        .name
+        .clone()
+        .or(source_name)
+        .unwrap_or_else(|| Local::now().format("image-%Y%m%d-%H%M%S").to_string());
+    let name = Path::new(&name);
+    let extension = match (args.png, name.extension()) {
+        (true, _) => "png".to_string(),
+        (false, Some(extension)) => extension.to_string_lossy().to_string(),
+        (false, None) => format.extensions_str()[0].to_string(),
+    };
+    let file_name = name.with_extension(extension);
+
+    let mut target = PathBuf::from(
+        [
+            config.config_base_path.clone().unwrap(),
+            config.image_dir.clone(),
+        ]
+        .join(MAIN_SEPARATOR_STR),
+    );
+    if let Some(folder) = &args.folder {
+        target.push(folder);
+    }
+    target.push(file_name);
+    if target.exists() && !args.force {
+        eprintln!(
+            "`{}` already exists, use --force to overwrite it",
+            target.display()
+        );
+        exit(1);
+    }
+    if let Some(dir) = target.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("Could not create the folder `{}`: {}", dir.display(), e);
+            exit(1);
+        }
+    }
+
+    let result = if args.crop_borders || args.max_size.is_some() || args.png || args.strip_metadata
+    {
+        process(&bytes, format, args, &target)
+    } else {
+        fs::write(&target, &bytes).map_err(|e| e.to_string())
+    };
+    if let Err(e) = result {
+        eprintln!("Could not add the image `{}`: {}", target.display(), e);
+        exit(1);
+    }
+    println!("Added `{}`.", target.display());
+
+    if !is_image(&target, config) {
+        println!(
+            "Note: the image is not shown, as its type is not in the image_types ({}).",
+            config.image_types.join(", ")
+        );
+    }
+}
+
+/// Reads the image and returns its file name, or None for stdin.
+fn read_source(source: &str) -> (Vec<u8>, Option<String>) {
+    if source == "-" {
+        let mut bytes = Vec::new();
+        if let Err(e) = io::stdin().read_to_end(&mut bytes) {
+            eprintln!("Could not read the image from stdin: {}", e);
+            exit(1);
+        }
+        return (bytes, None);
+    }
+
+    match fs::read(source) {
+        Ok(bytes) => (
+            bytes,
+            Path::new(source)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string()),
+        ),
+        Err(e) => {
+            eprintln!("Could not read the image `{}`: {}", source, e);
+            exit(1);
+        }
+    }
+}
+
+/// Decodes the image, applies the options and encodes it anew, which drops all metadata.
+fn process(bytes: &[u8], format: ImageFormat, args: &AddArgs, target: &Path) -> Result<(), String> {
+    let mut img = image::load_from_memory_with_format(bytes, format).map_err(|e| e.to_string())?;
+    if args.crop_borders {
+        img = crop_borders(img);
+    }
+    if let Some(max) = args.max_size {
+        if img.width() > max || img.height() > max {
+            img = img.resize(max, max, FilterType::Lanczos3);
+        }
+    }
+
+    let format = if args.png { ImageFormat::Png } else { format };
+    if format == ImageFormat::Jpeg {
+        // JPG has no transparency
+        img = DynamicImage::ImageRgb8(img.to_rgb8());
+    }
+    img.save_with_format(target, format)
+        .map_err(|e| e.to_string())
+}
+
+/// Crops the rows and columns at the edges that have the color of the top left pixel.
+fn crop_borders(img: DynamicImage) -> DynamicImage {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let border = *rgba.get_pixel(0, 0);
+    let is_border = |x: u32, y: u32| {
+        rgba.get_pixel(x, y)
+            .0
+            .iter()
+            .zip(border.0)
+            .all(|(a, b)| a.abs_diff(b) <= BORDER_TOLERANCE)
+    };
+    let is_border_row = |y: u32| (0..width).all(|x| is_border(x, y));
+    let is_border_column = |x: u32| (0..height).all(|y| is_border(x, y));
+
+    // an image of a single color has nothing but border
+    let Some(top) = (0..height).find(|&y| !is_border_row(y)) else {
+        return img;
+    };
+    let bottom = (0..height)
+        .rev()
+        .find(|&y| !is_border_row(y))
+        .unwrap_or(top);
+    let left = (0..width).find(|&x| !is_border_column(x)).unwrap_or(0);
+    let right = (0..width)
+        .rev()
+        .find(|&x| !is_border_column(x))
+        .unwrap_or(left);
+    img.crop_imm(left, top, right - left + 1, bottom - top + 1)
+}
@@ -1,38 +1,132 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
 
-use std::{fs::{self, metadata}, io, path::{Path, PathBuf}};
+use image::{ImageFormat, ImageReader, ImageResult};
+use viuer::{get_kitty_support, is_iterm_supported, KittySupport};
 
+use crate::filters::{self, PathFilter};
+use crate::index::indexed_images;
+use crate::ratings::Ratings;
+use crate::selection::{relative_path, Candidates, Weights};
 use crate::settings::NerdcliConfig;
 
-fn _list_files(vec: &mut Vec<PathBuf>, path: &Path, config: &NerdcliConfig) -> io::Result<()> {
-    if metadata(&path)?.is_dir() {
-        let paths = fs::read_dir(&path)?;
-        for path_result in paths {
-            let full_path = path_result?.path();
-            if metadata(&full_path)?.is_dir() {
-
-                if config.include_folders.is_empty() 
-                  || config.include_folders.iter().any(
-                    |f|full_path.ends_with(f)
-                ) {
-                    // println!("reading dir {}", full_path.display());
-                    _list_files(vec, &full_path, config)?
-                } else {
-                    // println!("not include {}", full_path.display());
-                }
-            } else {
-                if let Some(ext) = full_path.extension().and_then(|ext| ext.to_str()) {
-                    if config.image_types.is_empty() || config.image_types.contains(&ext.to_string()) {
-                        vec.push(full_path);
-                    }
-                }
-            }
+/// Normalizes a file extension or an entry of `image_types`, so that `PNG` matches `png`
+/// and `jpeg` matches `jpg`.
+fn normalize_type(ext: &str) -> String {
+    let ext = ext.trim_start_matches('.').to_lowercase();
+    match ext.as_str() {
+        "jpeg" | "jpe" | "jfif" => "jpg".to_string(),
+        "tif" => "tiff".to_string(),
+        _ => ext,
+    }
+}
+
+/// Reads the first bytes of a file to detect the image format.
+fn sniff_format(path: &Path) -> Option<ImageFormat> {
+    let mut buffer = [0u8; 32];
+    let read = File::open(path).ok()?.read(&mut buffer).ok()?;
+    image::guess_format(&buffer[..read]).ok()
+}
+
+/// Returns the normalized type of an image file and whether it was detected by the content,
+/// or None if the file is no image.
+/// Hidden files (e.g. `.DS_Store` or the `._*` files of macOS) are never images.
+/// Files without a known extension are only checked by their content if `sniff` is set.
+pub fn file_image_type(path: &Path, sniff: bool) -> Option<(String, bool)> {
+    let name = path.file_name()?.to_str()?;
+    if name.starts_with('.') {
+        return None;
+    }
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ImageFormat::from_extension(ext).is_some() => {
+            Some((normalize_type(ext), false))
+        }
+        _ if sniff => sniff_format(path)
+            .and_then(|f| f.extensions_str().first().copied())
+            .map(|t| (normalize_type(t), true)),
+        _ => None,
+    }
+}
+
+/// Checks the type of an image against the `image_types` and `sniff_images` settings.
+fn is_selected_type(image_type: &str, by_content: bool, config: &NerdcliConfig) -> bool {
+    (!by_content || config.sniff_images)
+        && (config.image_types.is_empty()
+            || config
+                .image_types
+                .iter()
+                .any(|it| normalize_type(it) == image_type))
+}
+
+pub fn is_image(path: &Path, config: &NerdcliConfig) -> bool {
+    match file_image_type(path, config.sniff_images) {
+        Some((t, by_content)) => is_selected_type(&t, by_content, config),
+        None => false,
+    }
+}
+
+/// The dimensions of an image in pixels. The format is detected by the content, so images
+/// without (or with a wrong) extension work as well.
+pub fn dimensions(path: &Path) -> ImageResult<(u32, u32)> {
+    ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_dimensions()
+}
+
+/// Lists all images in the image_dir (`path`) that are selected by the `image_filter` and not
+/// banned, with the weights of their folders and ratings. Unless `image_index = false`, the images are taken from the cached index.
+pub fn list_files(path: &Path, config: &NerdcliConfig) -> io::Result<Candidates> {
+    let filter = PathFilter::new(&config.image_filter)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("image_filter: {}", e)))?;
+
+    let (files, folder_weights) = if config.image_index.unwrap_or(true) {
+        let index = indexed_images(path, &filter, config.sniff_images)?;
+        let files: Vec<PathBuf> = index
+            .images()
+            .filter(|i| is_selected_type(&i.format, i.by_content, config))
+            .filter(|i| filter.is_match(Path::new(&i.path)))
+            .map(|i| path.join(&i.path))
+            .collect();
+        (files, index.folder_weights())
+    } else {
+        let files = filters::list_files(path, &filter, &|p| is_image(p, config))?;
+        let folder_weights = Weights::read_weight_files(path, &files);
+        (files, folder_weights)
+    };
+
+    // banned images are never shown, rated ones more or less often
+    let ratings = Ratings::load().images;
+    let files: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|f| !Ratings::is_banned(&ratings, &f.to_string_lossy()))
+        .collect();
+    let mut weights = Weights::new(&config.image_weights, folder_weights);
+    for file in &files {
+        if let Some(rating) = ratings.get(file.to_string_lossy().as_ref()) {
+            weights.set_rating(relative_path(path, file), rating.weight());
         }
     }
-    Ok(())
+
+    Ok(Candidates {
+        root: path.to_path_buf(),
+        files,
+        weights,
+    })
 }
 
-pub fn list_files(path: &Path, config: &NerdcliConfig) -> io::Result<Vec<PathBuf>> {
-    let mut vec = Vec::new();
-    _list_files(&mut vec, &path, config)?;
-    Ok(vec)
-}
\ No newline at end of file
+/// The graphics protocol viuer will use to print images (in the same order viuer checks them).
+pub fn detect_protocol() -> &'static str {
+    if is_iterm_supported() {
+        "iterm"
+    } else {
+        match get_kitty_support() {
+            KittySupport::Local => "kitty-local",
+            KittySupport::Remote => "kitty-remote",
+            KittySupport::None => "blocks",
+        }
+    }
+}
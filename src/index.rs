@@ -0,0 +1,492 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, Metadata},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::exit,
+    time::UNIX_EPOCH,
+};
+
+use clap::Subcommand;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::filters::PathFilter;
+use crate::images::{dimensions, file_image_type};
+use crate::selection::{read_weight_file, WEIGHT_FILE};
+use crate::settings::{parse_config, project_dirs, NerdcliConfig};
+use crate::Cli;
+
+/// Increase, if the format of the index file changes. Older index files are rebuilt.
+const INDEX_VERSION: u32 = 4;
+
+#[derive(Subcommand)]
+pub enum IndexCommand {
+    #[command(about = "Scan the whole image_dir again and replace the cached index")]
+    Rebuild,
+
+    #[command(about = "Print the location and the number of images of the cached index")]
+    Status,
+}
+
+/// An image file with the information that is needed to select it without touching the file.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexedImage {
+    /// The path relative to the image_dir, separated by `/`.
+    pub path: String,
+    pub modified: u64,
+    pub size: u64,
+    /// The normalized image type, e.g. `png` or `jpg`.
+    pub format: String,
+    /// True, if the type was detected by the content instead of the file extension.
+    pub by_content: bool,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// FNV-1a hash of the content, to recognize the same image after renaming or moving it.
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct IndexedDir {
+    modified: u64,
+    /// The content of the `.nerdcli-weight` file of the directory.
+    weight: Option<f64>,
+    /// Editing a file doesn't change the modification time of its directory, so the weight file
+    /// is checked on its own (0 without weight file).
+    weight_modified: u64,
+    subdirs: Vec<String>,
+    images: Vec<IndexedImage>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ImageIndex {
+    version: u32,
+    root: PathBuf,
+    /// The `sniff_images` setting the index was built with, files without an image extension
+    /// are only read with it.
+    sniff_images: bool,
+    /// Directories by their path relative to the image_dir ("" is the image_dir itself).
+    dirs: BTreeMap<String, IndexedDir>,
+}
+
+pub fn run(command: &IndexCommand, cli: &Cli) {
+    let config = parse_config(cli);
+    let root = image_root(&config);
+    match command {
+        IndexCommand::Rebuild => {
+            let filter = PathFilter::new(&config.image_filter).unwrap_or_else(|e| {
+                eprintln!("Invalid image_filter: {}", e);
+                exit(1);
+            });
+            let mut index = ImageIndex::empty(&root, config.sniff_images);
+            if let Err(e) = index.refresh(&root, &filter) {
+                eprintln!("Could not read the images in `{}`: {}", root.display(), e);
+                exit(1);
+            }
+            index.save();
+            println!(
+                "Indexed {} images in `{}`.",
+                index.images().count(),
+                root.display()
+            );
+        }
+        IndexCommand::Status => {
+            let index = ImageIndex::load(&root, config.sniff_images);
+            println!("Index file: {}", index_file(&root).display());
+            println!("Image directory: {}", root.display());
+            println!("Indexed images: {}", index.images().count());
+        }
+    }
+}
+
+pub fn image_root(config: &NerdcliConfig) -> PathBuf {
+    Path::new(config.config_base_path.as_deref().unwrap_or_default()).join(&config.image_dir)
+}
+
+/// Every image_dir (e.g. of different profiles) has its own index file.
+fn index_file(root: &Path) -> PathBuf {
+    let name = format!(
+        "images-{}.json",
+        fnv_hash(root.to_string_lossy().as_bytes())
+    );
+    project_dirs().cache_dir().join(name)
+}
+
+/// Returns all images in the image_dir. The cached index is only updated for directories that
+/// changed since the last run, so usually only the directories are checked, not the single files.
+/// Changes to the content of a file (without renaming it) are found by `nerdcli index rebuild`.
+/// Folders that are excluded completely by the `filter` are left out.
+pub fn indexed_images(
+    root: &Path,
+    filter: &PathFilter,
+    sniff_images: bool,
+) -> io::Result<ImageIndex> {
+    let mut index = ImageIndex::load(root, sniff_images);
+    if index.refresh(root, filter)? {
+        index.save();
+    }
+    Ok(index)
+}
+
+impl ImageIndex {
+    fn empty(root: &Path, sniff_images: bool) -> ImageIndex {
+        ImageIndex {
+            version: INDEX_VERSION,
+            root: root.to_path_buf(),
+            sniff_images,
+            dirs: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the cached index. A missing, outdated or broken index file, or one built with another
+    /// `sniff_images` setting, results in an empty index.
+    pub fn load(root: &Path, sniff_images: bool) -> ImageIndex {
+        fs::read_to_string(index_file(root))
+            .ok()
+            .and_then(|c| serde_json::from_str::<ImageIndex>(&c).ok())
+            .filter(|i| i.version == INDEX_VERSION && i.root == root)
+            .filter(|i| i.sniff_images == sniff_images)
+            .unwrap_or_else(|| ImageIndex::empty(root, sniff_images))
+    }
+
+    pub fn save(&self) {
+        let path = index_file(&self.root);
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| serde_json::to_string(self).map_err(io::Error::other))
+            .and_then(|json| {
+                // write to a temporary file first, so a parallel start never reads half an index
+                let tmp = path.with_extension("json.tmp");
+                fs::write(&tmp, json)?;
+                fs::rename(&tmp, &path)
+            });
+        if let Err(e) = result {
+            eprintln!(
+                "Could not write the image index `{}`: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    pub fn images(&self) -> impl Iterator<Item = &IndexedImage> {
+        self.dirs.values().flat_map(|d| d.images.iter())
+    }
+
+    /// The weights of all directories with a `.nerdcli-weight` file by their relative path.
+    pub fn folder_weights(&self) -> BTreeMap<String, f64> {
+        self.dirs
+            .iter()
+            .filter_map(|(path, dir)| Some((path.clone(), dir.weight?)))
+            .collect()
+    }
+
+    /// Updates the index. Returns true, if anything changed.
+    pub fn refresh(&mut self, root: &Path, filter: &PathFilter) -> io::Result<bool> {
+        let mut old = std::mem::take(&mut self.dirs);
+        let mut changed = false;
+        let sniff = self.sniff_images;
+        refresh_dir(
+            root,
+            "",
+            filter,
+            sniff,
+            &mut old,
+            &mut self.dirs,
+            &mut changed,
+        )?;
+        // directories that are left over were deleted or are excluded now
+        Ok(changed || !old.is_empty())
+    }
+}
+
+fn refresh_dir(
+    root: &Path,
+    relative: &str,
+    filter: &PathFilter,
+    sniff: bool,
+    old: &mut BTreeMap<String, IndexedDir>,
+    new: &mut BTreeMap<String, IndexedDir>,
+    changed: &mut bool,
+) -> io::Result<()> {
+    let path = root.join(relative);
+    let modified = modified(&fs::metadata(&path)?);
+
+    let mut dir = match old.remove(relative) {
+        Some(dir) if dir.modified == modified => dir,
+        previous => {
+            *changed = true;
+            scan_dir(
+                &path,
+                relative,
+                modified,
+                previous.unwrap_or_default(),
+                sniff,
+            )?
+        }
+    };
+
+    let weight_modified = fs::metadata(path.join(WEIGHT_FILE)).map_or(0, |m| self::modified(&m));
+    if dir.weight_modified != weight_modified {
+        *changed = true;
+        dir.weight = read_weight_file(&path);
+        dir.weight_modified = weight_modified;
+    }
+
+    for subdir in &dir.subdirs {
+        let subdir = join(relative, subdir);
+        if filter.excludes_dir(Path::new(&subdir)) {
+            continue;
+        }
+        if let Err(e) = refresh_dir(root, &subdir, filter, sniff, old, new, changed) {
+            eprintln!("Skipping `{}`: {}", root.join(&subdir).display(), e);
+        }
+    }
+    new.insert(relative.to_string(), dir);
+    Ok(())
+}
+
+/// Reads a directory that is new or changed. Images that did not change are taken from `previous`.
+/// Files that can't be read (e.g. broken symlinks) are skipped with a warning.
+fn scan_dir(
+    path: &Path,
+    relative: &str,
+    modified: u64,
+    previous: IndexedDir,
+    sniff: bool,
+) -> io::Result<IndexedDir> {
+    let mut previous_images: BTreeMap<String, IndexedImage> = previous
+        .images
+        .into_iter()
+        .map(|i| (i.path.clone(), i))
+        .collect();
+    let mut dir = IndexedDir {
+        modified,
+        ..Default::default()
+    };
+
+    for entry in fs::read_dir(path)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("Skipping a file in `{}`: {}", path.display(), e);
+                continue;
+            }
+        };
+        let full_path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let metadata = match fs::metadata(&full_path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("Skipping `{}`: {}", full_path.display(), e);
+                continue;
+            }
+        };
+        if metadata.is_dir() {
+            dir.subdirs.push(name);
+            continue;
+        }
+        if name == WEIGHT_FILE {
+            // read by refresh_dir
+            continue;
+        }
+
+        let image_path = join(relative, &name);
+        let (size, modified) = (metadata.len(), self::modified(&metadata));
+        match previous_images.remove(&image_path) {
+            Some(image) if image.size == size && image.modified == modified => {
+                dir.images.push(image)
+            }
+            _ => {
+                if let Some((format, by_content)) = file_image_type(&full_path, sniff) {
+                    let hash = match hash_file(&full_path) {
+                        Ok(hash) => hash,
+                        Err(e) => {
+                            eprintln!("Skipping `{}`: {}", full_path.display(), e);
+                            continue;
+                        }
+                    };
+                    let (width, height) = match dimensions(&full_path) {
+                        Ok((w, h)) => (Some(w), Some(h)),
+                        Err(_) => (None, None),
+                    };
+                    dir.images.push(IndexedImage {
+                        path: image_path,
+                        modified,
+                        size,
+                        format,
+                        by_content,
+                        width,
+                        height,
+                        hash,
+                    });
+                }
+            }
+        }
+    }
+
+    dir.subdirs.sort();
+    dir.images.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(dir)
+}
+
+fn join(relative: &str, name: &str) -> String {
+    if relative.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", relative, name)
+    }
+}
+
+fn modified(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+pub fn fnv_hash(bytes: &[u8]) -> String {
+    format!("{:016x}", fnv_hash64(bytes))
+}
+
+pub fn fnv_hash64(bytes: &[u8]) -> u64 {
+    let mut hash = FNV_OFFSET;
+    fnv_update(&mut hash, bytes);
+    hash
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+fn fnv_update(hash: &mut u64, bytes: &[u8]) {
+    for byte in bytes {
+        *hash ^= *byte as u64;
+        *hash = hash.wrapping_mul(0x100000001b3);
+    }
+}
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut buffer = [0u8; 64 * 1024];
+    let mut hash = FNV_OFFSET;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        fnv_update(&mut hash, &buffer[..read]);
+    }
+    Ok(format!("{:016x}", hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image_dir with `a.png` and `comics/b.png` in a temporary folder of its own.
+    fn image_dir(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("nerdcli-index-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("comics")).unwrap();
+        fs::write(root.join("a.png"), "a").unwrap();
+        fs::write(root.join("comics/b.png"), "b").unwrap();
+        root
+    }
+
+    fn everything() -> PathFilter {
+        PathFilter::new(&[]).unwrap()
+    }
+
+    fn paths(index: &ImageIndex) -> Vec<&str> {
+        index.images().map(|i| i.path.as_str()).collect()
+    }
+
+    #[test]
+    fn reuses_unchanged_dirs() {
+        let root = image_dir("unchanged");
+        let mut index = ImageIndex::empty(&root, false);
+        assert!(index.refresh(&root, &everything()).unwrap());
+        assert_eq!(paths(&index), ["a.png", "comics/b.png"]);
+
+        assert!(!index.refresh(&root, &everything()).unwrap());
+        assert_eq!(paths(&index), ["a.png", "comics/b.png"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rescans_changed_dirs() {
+        let root = image_dir("changed");
+        let mut index = ImageIndex::empty(&root, false);
+        index.refresh(&root, &everything()).unwrap();
+
+        fs::write(root.join("comics/c.png"), "c").unwrap();
+        fs::remove_file(root.join("a.png")).unwrap();
+        assert!(index.refresh(&root, &everything()).unwrap());
+        assert_eq!(paths(&index), ["comics/b.png", "comics/c.png"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn drops_deleted_dirs() {
+        let root = image_dir("deleted");
+        let mut index = ImageIndex::empty(&root, false);
+        index.refresh(&root, &everything()).unwrap();
+
+        fs::remove_dir_all(root.join("comics")).unwrap();
+        assert!(index.refresh(&root, &everything()).unwrap());
+        assert_eq!(paths(&index), ["a.png"]);
+        assert!(!index.dirs.contains_key("comics"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn picks_up_edited_weight_files() {
+        let root = image_dir("weights");
+        let weight_file = root.join("comics").join(WEIGHT_FILE);
+        fs::write(&weight_file, "2").unwrap();
+        let mut index = ImageIndex::empty(&root, false);
+        index.refresh(&root, &everything()).unwrap();
+        assert_eq!(index.folder_weights().get("comics"), Some(&2.0));
+
+        // editing the file doesn't change the modification time of the folder
+        fs::write(&weight_file, "5").unwrap();
+        assert!(index.refresh(&root, &everything()).unwrap());
+        assert_eq!(index.folder_weights().get("comics"), Some(&5.0));
+
+        fs::remove_file(&weight_file).unwrap();
+        assert!(index.refresh(&root, &everything()).unwrap());
+        assert!(index.folder_weights().is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn sniffs_files_without_extension_only_if_enabled() {
+        let root = image_dir("sniff");
+        fs::write(root.join("scan"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+
+        let mut index = ImageIndex::empty(&root, false);
+        index.refresh(&root, &everything()).unwrap();
+        assert_eq!(paths(&index), ["a.png", "comics/b.png"]);
+
+        let mut index = ImageIndex::empty(&root, true);
+        index.refresh(&root, &everything()).unwrap();
+        assert_eq!(paths(&index), ["a.png", "scan", "comics/b.png"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn leaves_out_excluded_dirs() {
+        let root = image_dir("excluded");
+        let mut index = ImageIndex::empty(&root, false);
+        let filter = PathFilter::new(&["!comics/**".to_string()]).unwrap();
+        index.refresh(&root, &filter).unwrap();
+        assert_eq!(paths(&index), ["a.png"]);
+
+        // without the exclusion, the folder is read on the next start
+        assert!(index.refresh(&root, &everything()).unwrap());
+        assert_eq!(paths(&index), ["a.png", "comics/b.png"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+use std::{collections::BTreeMap, env, fmt};
+
+use serde_derive::{Deserialize, Serialize};
+
+/// The languages to select quotes in, i.e. folder names in the quotes_dir: a list that are all
+/// selected equally often (`["en", "de"]`) or a table with weights (`{ en = 2, de = 1 }`).
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum QuoteLanguages {
+    List(Vec<String>),
+    Weighted(BTreeMap<String, f64>),
+}
+
+impl Default for QuoteLanguages {
+    fn default() -> Self {
+        QuoteLanguages::List(Vec::new())
+    }
+}
+
+impl QuoteLanguages {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            QuoteLanguages::List(languages) => languages.is_empty(),
+            QuoteLanguages::Weighted(languages) => languages.is_empty(),
+        }
+    }
+
+    /// The languages with their weights. Without any language, the language of the locale is used.
+    pub fn weighted(&self) -> Vec<(String, f64)> {
+        if self.is_empty() {
+            return vec![(locale_language(), 1.0)];
+        }
+        match self {
+            QuoteLanguages::List(languages) => languages.iter().map(|l| (l.clone(), 1.0)).collect(),
+            QuoteLanguages::Weighted(languages) => {
+                languages.iter().map(|(l, w)| (l.clone(), *w)).collect()
+            }
+        }
+    }
+}
+
+impl fmt::Display for QuoteLanguages {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            _ if self.is_empty() => write!(f, "{} (from the locale)", locale_language()),
+            QuoteLanguages::List(languages) => write!(f, "{}", languages.join(", ")),
+            QuoteLanguages::Weighted(languages) => {
+                let languages: Vec<String> = languages
+                    .iter()
+                    .map(|(l, w)| format!("{} = {}", l, w))
+                    .collect();
+                write!(f, "{}", languages.join(", "))
+            }
+        }
+    }
+}
+
+/// The language of the user's locale, e.g. `de` for `LANG=de_DE.UTF-8`, read from LC_ALL,
+/// LC_MESSAGES and LANG (in this order). Falls back to `en`.
+pub fn locale_language() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .map(|locale| {
+            locale
+                .split(['_', '.', '@'])
+                .next()
+                .unwrap_or_default()
+                .to_lowercase()
+        })
+        .find(|language| !language.is_empty() && language != "c" && language != "posix")
+        .unwrap_or_else(|| "en".to_string())
+}
@@ -1,19 +1,55 @@
+mod cache;
+mod config_commands;
+mod content;
+mod crop;
+mod debug;
 mod default_settings;
+mod effects;
+mod filters;
+mod history;
+mod image_commands;
 mod images;
+mod index;
+mod languages;
+mod migrations;
+mod profiles;
+mod quote_commands;
+mod quote_filter;
+mod quote_formats;
+mod quote_layout;
 mod quotes;
+mod ratings;
+mod schema;
+mod selection;
 mod settings;
+mod tags;
 
 extern crate clap;
-use clap::Parser;
-use image::{image_dimensions, ImageResult};
-use images::list_files;
+use cache::CacheCommand;
+use clap::{Parser, Subcommand};
+use config_commands::ConfigCommand;
+use content::ImageAsset;
+use debug::{
+    DebugFormat, DebugReport, ImageInfo, LayoutInfo, QuoteInfo, Rect, TerminalInfo, Timings,
+};
+use effects::ImageEffect;
+use history::History;
+use image::ImageResult;
+use image_commands::ImageCommand;
+use images::{detect_protocol, dimensions, list_files};
+use index::IndexCommand;
+use quote_commands::QuoteCommand;
+use quote_filter::QuoteFilter;
+use quote_layout::{quote_lines, QUOTE_BOX_WIDTH};
 use quotes::{get_quote, Quote};
-use rand::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+use ratings::{Change, MarkArgs, RateArgs};
 use settings::{parse_config, print_config, NerdcliConfig};
-use std::path::{Path, MAIN_SEPARATOR};
+use std::path::{Path, MAIN_SEPARATOR_STR};
+use std::process::exit;
 use viuer::{print_from_file, Config as ImageConfig};
 
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
 use std::io::{stdout, Write};
 use termion::color;
 use termion::raw::IntoRawMode;
@@ -23,8 +59,19 @@ use termion::raw::IntoRawMode;
 #[command(version = "1.0")]
 #[command(about = "Add a bit of nerdyness to your terminal", long_about = None)]
 struct Cli {
-    #[arg(short, long, help = "Sets the debug mode")]
-    debug: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(
+        short,
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text",
+        help = "Sets the debug mode. With --debug=json, a JSON document is printed to stderr"
+    )]
+    debug: Option<DebugFormat>,
 
     #[arg(
         short,
@@ -36,6 +83,7 @@ struct Cli {
     #[arg(
         short = 'x',
         long,
+        global = true,
         help = "Overwrite the max_width_percentage from the config file"
     )]
     max_width_percentage: Option<u16>,
@@ -43,6 +91,7 @@ struct Cli {
     #[arg(
         short = 'y',
         long,
+        global = true,
         help = "Overwrite the max_height_percentage from the config file"
     )]
     max_height_percentage: Option<u16>,
@@ -50,6 +99,7 @@ struct Cli {
     #[arg(
         short,
         long,
+        global = true,
         help = "Overwrite the margin left of the image from the config file"
     )]
     left: Option<u16>,
@@ -57,6 +107,7 @@ struct Cli {
     #[arg(
         short,
         long,
+        global = true,
         help = "Overwrite the margin above the image from the config file"
     )]
     above: Option<i16>,
@@ -64,6 +115,7 @@ struct Cli {
     #[arg(
         short = 's',
         long,
+        global = true,
         help = "Overwrite the layout set in the config file: [ROW | ROW_CENTERED | COL | COL_CENTERED]"
     )]
     layout: Option<String>,
@@ -74,41 +126,155 @@ struct Cli {
         help = "Set a specific image (absolute path) that will be displayed. Good for testing layouts."
     )]
     image: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Use the settings of a profile from the config file (overrides NERDCLI_PROFILE and the automatic selection)"
+    )]
+    profile: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Only show quotes whose author or character contains this text"
+    )]
+    author: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Only show quotes with this tag (can be given more than once)"
+    )]
+    tag: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Only show quotes whose source or work contains this text"
+    )]
+    source: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Only show quotes with at most this many lines"
+    )]
+    max_lines: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Only show quotes whose text matches this regular expression"
+    )]
+    grep: Option<String>,
+
+    #[arg(
+        long,
+        help = "Select image and quote from this seed, e.g. for reproducible screenshots (see --debug=json for the seed of a run)"
+    )]
+    seed: Option<u64>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(subcommand, about = "Show, query and change the configuration")]
+    Config(ConfigCommand),
+
+    #[command(subcommand, about = "Manage the cached index of the images")]
+    Index(IndexCommand),
+
+    #[command(subcommand, about = "Manage the cache of downscaled images")]
+    Cache(CacheCommand),
+
+    #[command(
+        subcommand,
+        about = "Add quotes to the collection or import them from other files"
+    )]
+    Quote(QuoteCommand),
+
+    #[command(subcommand, about = "Add images to the image_dir")]
+    Image(ImageCommand),
+
+    #[command(
+        about = "Mark the last shown image (or quote) as favorite, so it is shown more often"
+    )]
+    Fav(MarkArgs),
+
+    #[command(about = "Never show the last shown image (or quote) again")]
+    Ban(MarkArgs),
+
+    #[command(
+        about = "Rate the last shown image (or quote), higher rated ones are shown more often"
+    )]
+    Rate(RateArgs),
+}
+
+const MIN_QUOTE_WIDTH: f32 = 50.0;
+const QUOTE_MARGIN: f32 = 5.0;
+const PROMT_HEIGHT: f32 = 16.0; // TODO make this configurable, as some prompts are multiple lines in height
+
+/// How many lines of quote text (plus the lines of source and author) fit into the terminal next
+/// to (or below) the image. Longer quotes are not selected.
+fn fitting_quote_lines(config: &NerdcliConfig) -> Option<usize> {
+    let (_, rows) = termion::terminal_size().ok()?;
+    let top_margin = config.margin_top.unwrap_or(0) as f32;
+    let mut available = rows as f32 - top_margin - 3.0;
+
+    if matches!(config.layout.as_deref(), Some("COL" | "COL_CENTERED")) {
+        // the quote is below the image, which takes up to max_height_percentage of the terminal
+        let max_height_percentage = config.max_height_percentage.unwrap_or(100) as f32;
+        let image_rows = (rows as f32 - PROMT_HEIGHT - top_margin) * max_height_percentage / 100.0;
+        available -= image_rows + QUOTE_MARGIN;
+    }
+    Some(available.max(1.0) as usize)
+}
+
+/// The aspect ratio (height / width, in pixels) of the space for the image: next to the quote in
+/// the ROW layouts, above it in the COL layouts. Images are cropped to it with `crop_to_fit`.
+fn image_area_ratio(config: &NerdcliConfig) -> Option<f32> {
+    let (columns, rows) = termion::terminal_size().ok()?;
+    let top_margin = config.margin_top.unwrap_or(0) as f32;
+    let left_margin = config.margin_left.unwrap_or(0) as f32;
+    // a cell shows two pixels in height, like in calculate_image_layout
+    let height = (rows as f32 - PROMT_HEIGHT - top_margin) * 2.0;
+    let max_width = columns as f32 * config.max_width_percentage.unwrap_or(100) as f32 / 100.0;
+    let max_height = height * config.max_height_percentage.unwrap_or(100) as f32 / 100.0;
+
+    let (width, height) = if matches!(config.layout.as_deref(), Some("COL" | "COL_CENTERED")) {
+        (max_width.min(columns as f32), max_height)
+    } else {
+        let beside_quote = columns as f32 - MIN_QUOTE_WIDTH - QUOTE_MARGIN - 2.0 * left_margin;
+        (
+            max_width.min(beside_quote),
+            height.min(max_height - 2.0 * top_margin),
+        )
+    };
+    (width > 0.0 && height > 0.0).then(|| height / width)
 }
 
 fn calculate_image_layout(
     image: &String,
     config: &NerdcliConfig,
-    cli: &Cli,
     quote_line_length: u16,
 ) -> (Option<u32>, Option<u32>, f32, f32, u16, u16) {
-    const MIN_QUOTE_WIDTH: f32 = 50 as f32;
-    const QUOTE_MARGIN: f32 = 5 as f32;
-    // const MIN_QUOTE_HEIGHT: f32 = 25 as f32;
-    const PROMT_HEIGHT: f32 = 16 as f32; // TODO make this configurable, as some prompts are multiple lines in height
+    // const MIN_QUOTE_HEIGHT: f32 = 25.0;
 
-    let top_margin = if cli.above.is_some() {
-        cli.above.unwrap()
-    } else {
-        config.margin_top.unwrap()
-    };
-    let left_margin = if cli.left.is_some() {
-        cli.left.unwrap()
-    } else {
-        config.margin_left.unwrap()
-    };
+    let top_margin = config.margin_top.unwrap_or(0);
+    let left_margin = config.margin_left.unwrap_or(0);
 
     let (terminal_cells_width, tch) = termion::terminal_size().unwrap();
 
     // the factor 2 is due to the fact, that each cell in the terminal is double the width of a cell
     // the -16 is due to the fact, that after the image a newline and the prompt is printed
-    let terminal_cells_height = (tch as f32 - PROMT_HEIGHT - top_margin as f32) * 2 as f32;
+    let terminal_cells_height = (tch as f32 - PROMT_HEIGHT - top_margin as f32) * 2.0;
     // println!("Terminal size: {}x{}", terminal_cells_width, terminal_cells_height);
 
-    let dim: ImageResult<(u32, u32)> = image_dimensions(Path::new(&image));
+    let dim: ImageResult<(u32, u32)> = dimensions(Path::new(&image));
     let (_width, _height, ratio) = match dim {
         Ok((w, h)) => {
-            let r = (h as f32 / w as f32) as f32;
+            let r = h as f32 / w as f32;
             // println!("Image dimensions: {}x{} px. Ratio: {}", w, h, r);
 
             (w, h, r)
@@ -119,40 +285,24 @@ fn calculate_image_layout(
     let mut x: f32 = left_margin as f32;
     let y: f32 = top_margin as f32;
 
-    let max_width_percentage: f32 = if cli.max_width_percentage.is_some() {
-        cli.max_width_percentage.unwrap() as f32
-    } else {
-        config.max_width_percentage.unwrap() as f32
-    };
-    let max_height_percentage: f32 = if cli.max_height_percentage.is_some() {
-        cli.max_height_percentage.unwrap() as f32
-    } else {
-        config.max_height_percentage.unwrap() as f32
-    };
-
-    let layout = if cli.layout.is_some() {
-        cli.layout.clone().unwrap()
-    } else {
-        config.layout.clone().unwrap()
-    };
+    let max_width_percentage = config.max_width_percentage.unwrap_or(100) as f32;
+    let max_height_percentage = config.max_height_percentage.unwrap_or(100) as f32;
 
-    let _show_quotes = config.show_quotes;
+    let layout = config.layout.clone().unwrap_or("ROW".to_string());
 
     if config.show_quotes {
-        let boundary_image_width: f32 =
-            terminal_cells_width as f32 * max_width_percentage / 100 as f32; // this is not restricted by the quote width and margins, as the image might be smaller
-        let boundary_image_height: f32 =
-            terminal_cells_height as f32 * max_height_percentage / 100 as f32;
+        let boundary_image_width: f32 = terminal_cells_width as f32 * max_width_percentage / 100.0; // this is not restricted by the quote width and margins, as the image might be smaller
+        let boundary_image_height: f32 = terminal_cells_height * max_height_percentage / 100.0;
 
         if layout == "ROW" || layout == "ROW_CENTERED" {
             let mut current_image_width: f32 = boundary_image_width;
-            let mut current_image_height = current_image_width as f32 * ratio;
+            let mut current_image_height = current_image_width * ratio;
             let mut current_quote_width = terminal_cells_width as f32 - current_image_width;
 
             // reduce the image size as long as the heigt is too high for the terminal or the quote would not fit
             while current_quote_width < MIN_QUOTE_WIDTH
-                || current_image_height > terminal_cells_height as f32
-                || current_image_height > (boundary_image_height - (y as f32 * 2 as f32))
+                || current_image_height > terminal_cells_height
+                || current_image_height > (boundary_image_height - (y * 2.0))
                 || (terminal_cells_width as f32)
                     < current_image_width
                         + MIN_QUOTE_WIDTH
@@ -160,103 +310,116 @@ fn calculate_image_layout(
                         + left_margin as f32
                         + left_margin as f32
             {
-                current_image_width -= 1 as f32;
-                current_image_height -= 2 as f32;
-                // current_image_height = current_image_width as f32 * ratio;
+                current_image_width -= 1.0;
+                current_image_height -= 2.0;
+                // current_image_height = current_image_width * ratio;
                 current_quote_width = terminal_cells_width as f32 - current_image_width;
             }
 
             // calculate the y position of the quote:
-            current_image_height = current_image_width as f32 * ratio - y as f32; // 5 sind die zwei Zeilen für den Prompt
+            current_image_height = current_image_width * ratio - y; // 5 sind die zwei Zeilen für den Prompt
 
-            let quote_y = ((current_image_height as f32 / 2 as f32) - quote_line_length as f32)
-                / 2 as f32
-                + top_margin as f32;
+            let quote_y =
+                ((current_image_height / 2.0) - quote_line_length as f32) / 2.0 + top_margin as f32;
 
             let quote_x = if layout == "ROW_CENTERED" {
                 x = (terminal_cells_width as f32
                     - current_image_width
                     - MIN_QUOTE_WIDTH
                     - QUOTE_MARGIN)
-                    / 2 as f32;
-                current_image_width + x as f32 + QUOTE_MARGIN
+                    / 2.0;
+                current_image_width + x + QUOTE_MARGIN
             } else {
                 // x is already left_margin
-                current_image_width + x as f32 + QUOTE_MARGIN
+                current_image_width + x + QUOTE_MARGIN
             };
 
-            return (
+            (
                 Some(current_image_width as u32),
                 None,
                 x,
                 y,
                 quote_x as u16,
                 quote_y as u16,
-            );
+            )
         } else {
             let mut current_image_height: f32 = boundary_image_height;
-            let mut current_image_width: f32 = current_image_height as f32 / ratio;
-            // let mut current_quote_height = terminal_cells_height as f32  - current_image_height;
+            let mut current_image_width: f32 = current_image_height / ratio;
+            // let mut current_quote_height = terminal_cells_height  - current_image_height;
 
             while current_image_width > terminal_cells_width as f32
                 || current_image_width > boundary_image_width
             {
-                current_image_width -= 1 as f32;
-                current_image_height -= 2 as f32;
+                current_image_width -= 1.0;
+                current_image_height -= 2.0;
             }
 
             // calculate the x position of the quote:
-            current_image_width = current_image_height as f32 / ratio;
+            current_image_width = current_image_height / ratio;
 
-            let quote_x = terminal_cells_width as f32 / 2 as f32 - MIN_QUOTE_WIDTH / 2 as f32; //-(current_image_width as f32 - MIN_QUOTE_WIDTH as f32) / 2 as f32 + x as f32;
+            let quote_x = terminal_cells_width as f32 / 2.0 - MIN_QUOTE_WIDTH / 2.0; //-(current_image_width - MIN_QUOTE_WIDTH as f32) / 2.0 + x;
 
             let quote_y = if layout == "COL_CENTERED" {
-                x = (terminal_cells_width as f32 - current_image_width) / 2 as f32;
-                current_image_height / 2 as f32 + y as f32 + QUOTE_MARGIN
+                x = (terminal_cells_width as f32 - current_image_width) / 2.0;
+                current_image_height / 2.0 + y + QUOTE_MARGIN
             } else {
                 // x is already top_margin
-                current_image_height / 2 as f32 + y as f32 + QUOTE_MARGIN
+                current_image_height / 2.0 + y + QUOTE_MARGIN
             };
 
-            return (
+            (
                 Some(current_image_width as u32),
                 None,
                 x,
                 y,
                 quote_x as u16,
                 quote_y as u16,
-            );
+            )
         }
-    } else if !config.show_quotes {
-        //
-        return (Some(0 as u32), None, 0 as f32, 0 as f32, 0 as u16, 0 as u16);
+    } else {
+        (Some(0), None, 0.0, 0.0, 0, 0)
     }
-
-    (Some(0 as u32), None, 0 as f32, 0 as f32, 0 as u16, 0 as u16)
 }
 
-fn calculate_quote_layout(quote: Quote, quote_box_width: u16) -> Vec<String> {
-    // split the quote into lines only in word breaks and only if one line is longer than quote_box_width
-    let mut lines: Vec<String> = Vec::new();
-    let mut current_line = String::new();
-    let mut current_line_length = 0;
-
-    for natural_line in quote.text.split("\n") {
-        for word in natural_line.split_whitespace() {
-            if current_line_length + word.len() > quote_box_width as usize {
-                lines.push(current_line.clone());
-                current_line.clear();
-                current_line_length = 0;
-            }
-            current_line.push_str(word);
-            current_line.push_str(" ");
-            current_line_length += word.len() + 1;
-        }
-        lines.push(current_line.clone());
-        current_line.clear();
-        current_line_length = 0;
+/// The attribution line of quotes with a character, work or year, e.g.
+/// `— Roy Batty, Blade Runner (1982)`. The character (who says it in the fiction) is printed bold,
+/// the work italic. Returns the width and the colored line, or None for other quotes.
+fn attribution(quote: &Quote, settings: &NerdcliConfig) -> Option<(usize, String)> {
+    if quote.character.is_none() && quote.work.is_none() && quote.year.is_none() {
+        return None;
+    }
+
+    let who = quote.character.as_deref().unwrap_or(&quote.author);
+    let who_colored = who.truecolor(
+        settings.author_color.r,
+        settings.author_color.g,
+        settings.author_color.b,
+    );
+    let mut plain = format!("— {}", who);
+    let mut line = match quote.character {
+        Some(_) => format!("— {}", who_colored.bold()),
+        None => format!("— {}", who_colored),
+    };
+
+    let source_color = |text: ColoredString| {
+        text.truecolor(
+            settings.source_color.r,
+            settings.source_color.g,
+            settings.source_color.b,
+        )
+    };
+    if let Some(work) = quote.work.as_ref().or(quote.source.as_ref()) {
+        plain.push_str(&format!(", {}", work));
+        line.push_str(&format!(", {}", source_color(work.italic())));
+    }
+    if let Some(year) = quote.year.map(|y| y.to_string()).or(quote.date.clone()) {
+        plain.push_str(&format!(" ({})", year));
+        line.push_str(&format!(
+            " {}",
+            source_color(format!("({})", year).normal())
+        ));
     }
-    lines
+    Some((plain.chars().count(), line))
 }
 
 fn print_quote(
@@ -286,7 +449,7 @@ fn print_quote(
         );
     }
 
-    println!("");
+    println!();
 
     let max_line_length = quote_in_lines
         .iter()
@@ -294,52 +457,91 @@ fn print_quote(
         .max()
         .unwrap_or(0) as u16;
 
-    let source = format!(
-        "{} {}",
-        quote.source.unwrap_or("".to_string()),
-        quote.date.unwrap_or("".to_string())
-    );
-
-    if source.len() > 0 {
+    if let Some((width, line)) = attribution(&quote, settings) {
+        let right = quote_x + max_line_length.saturating_sub(1);
+        if quote.character.is_some() && !quote.author.is_empty() {
+            // who wrote it, above the attribution line, like the source of other quotes
+            write!(
+                stdout,
+                "{}{}",
+                termion::cursor::Goto(
+                    right.saturating_sub(quote.author.chars().count() as u16),
+                    quote_y + quote_in_lines.len() as u16 + 2
+                ),
+                termion::clear::UntilNewline
+            )
+            .unwrap();
+            println!(
+                "{}",
+                quote.author.italic().truecolor(
+                    settings.source_color.r,
+                    settings.source_color.g,
+                    settings.source_color.b
+                )
+            );
+        }
         write!(
             stdout,
-            "{} {}",
+            "{}{}",
             termion::cursor::Goto(
-                quote_x + max_line_length - 1 - source.len() as u16 - 1,
-                quote_y + quote_in_lines.len() as u16 + 2
+                right.saturating_sub(width as u16),
+                quote_y + quote_in_lines.len() as u16 + 3
             ),
             termion::clear::UntilNewline
         )
         .unwrap();
-        println!(
-            "{}",
-            source.italic().truecolor(
-                settings.source_color.r,
-                settings.source_color.g,
-                settings.source_color.b
-            )
+        println!("{}", line);
+    } else {
+        let source = format!(
+            "{} {}",
+            quote.source.unwrap_or_default(),
+            quote.date.unwrap_or_default()
         );
-    }
 
-    let author = quote.author;
-    write!(
-        stdout,
-        "{}{}",
-        termion::cursor::Goto(
-            quote_x + max_line_length - 1 - author.len() as u16 - 3,
-            quote_y + quote_in_lines.len() as u16 + 3
-        ),
-        termion::clear::UntilNewline
-    )
-    .unwrap();
-    println!(
-        "-- {}",
-        author.truecolor(
-            settings.author_color.r,
-            settings.author_color.g,
-            settings.author_color.b
-        )
-    );
+        if !source.is_empty() {
+            write!(
+                stdout,
+                "{} {}",
+                termion::cursor::Goto(
+                    quote_x + max_line_length - 1 - source.len() as u16 - 1,
+                    quote_y + quote_in_lines.len() as u16 + 2
+                ),
+                termion::clear::UntilNewline
+            )
+            .unwrap();
+            println!(
+                "{}",
+                source.italic().truecolor(
+                    settings.source_color.r,
+                    settings.source_color.g,
+                    settings.source_color.b
+                )
+            );
+        }
+
+        // e.g. fortunes without attribution
+        let author = quote.author;
+        if !author.is_empty() {
+            write!(
+                stdout,
+                "{}{}",
+                termion::cursor::Goto(
+                    quote_x + max_line_length - 1 - author.len() as u16 - 3,
+                    quote_y + quote_in_lines.len() as u16 + 3
+                ),
+                termion::clear::UntilNewline
+            )
+            .unwrap();
+            println!(
+                "-- {}",
+                author.truecolor(
+                    settings.author_color.r,
+                    settings.author_color.g,
+                    settings.author_color.b
+                )
+            );
+        }
+    }
 
     // reset
     println!("{}", color::Fg(color::Reset));
@@ -348,7 +550,23 @@ fn print_quote(
 fn main() {
     let cli = Cli::parse();
 
-    let config = parse_config();
+    if let Some(command) = &cli.command {
+        match command {
+            Command::Config(c) => config_commands::run(c, &cli),
+            Command::Index(c) => index::run(c, &cli),
+            Command::Cache(c) => cache::run(c),
+            Command::Quote(c) => quote_commands::run(c, &cli),
+            Command::Image(c) => image_commands::run(c, &cli),
+            Command::Fav(a) => ratings::run(&a.target, Change::Favorite(!a.undo)),
+            Command::Ban(a) => ratings::run(&a.target, Change::Banned(!a.undo)),
+            Command::Rate(a) => ratings::run(&a.target, Change::Stars(a.stars)),
+        }
+        return;
+    }
+
+    let mut timings = Timings::start();
+    let config = parse_config(&cli);
+    timings.lap("config");
 
     print!("{}[2J", 27 as char); // Clear the terminal
 
@@ -356,25 +574,54 @@ fn main() {
         config.config_base_path.clone().unwrap(),
         config.image_dir.clone(),
     ]
-    .join(&MAIN_SEPARATOR.to_string());
-
-    let allimages: Vec<String> = list_files(Path::new(&image_path), &config)
-        .unwrap()
+    .join(MAIN_SEPARATOR_STR);
+
+    let candidates = list_files(Path::new(&image_path), &config).unwrap_or_else(|e| {
+        eprintln!("Could not read the images in `{}`: {}", image_path, e);
+        exit(1);
+    });
+    let allimages: Vec<String> = candidates
+        .files
         .iter()
         .map(|x| x.to_string_lossy().into_owned())
         .collect();
+    timings.lap("list_images");
+
+    // with a fixed seed, the same image and quote must be selected, no matter what was shown before
+    let fixed_seed = cli.seed.or(config.selection.unwrap_or_default().seed());
+    let seed = fixed_seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut history = match fixed_seed {
+        Some(_) => History::default(),
+        None => History::load(),
+    };
+    let avoid_repeats = config.avoid_repeats.unwrap_or(0);
+    let image_selection = config.image_selection.unwrap_or_default();
+    let shuffle = image_selection.is_shuffle();
 
     // image is either a manually set path, a selected path from the folders or "".
-    let image = if cli.image.is_some() {
-        cli.image.clone().unwrap()
+    let image = if let Some(image) = cli.image.clone() {
+        image
     } else {
-        let mut rng = rand::rng();
-        let selected_image = allimages.choose(&mut rng);
-        if selected_image.is_none() {
-            println!("No images found in the directory");
-            "".to_string()
-        } else {
-            selected_image.unwrap().to_string()
+        let track = &mut history.images;
+        if shuffle {
+            let keys: Vec<String> = candidates
+                .selectable()
+                .map(|p| p.to_string_lossy().into_owned())
+                .collect();
+            track.start_cycle(&keys);
+        }
+        let skip = |p: &Path| track.skip(&p.to_string_lossy(), avoid_repeats, shuffle);
+        match candidates.choose(image_selection, skip, &mut rng) {
+            Some(selected_image) => {
+                let selected_image = selected_image.to_string_lossy().into_owned();
+                track.record(&selected_image, &selected_image, avoid_repeats, shuffle);
+                selected_image
+            }
+            None => {
+                println!("No images found in the directory");
+                "".to_string()
+            }
         }
     };
 
@@ -382,16 +629,68 @@ fn main() {
         config.config_base_path.clone().unwrap(),
         config.quotes_dir.clone(),
     ]
-    .join(&MAIN_SEPARATOR.to_string());
-    let quote = get_quote(Path::new(&quote_path), &config);
-    let quote_in_lines = calculate_quote_layout(quote.clone(), 50 as u16);
-
-    let (w, h, x, y, quote_x, quote_y) = calculate_image_layout(
-        &image,
+    .join(MAIN_SEPARATOR_STR);
+    let image_asset =
+        (!image.is_empty()).then(|| ImageAsset::load(Path::new(&image_path), Path::new(&image)));
+    let fitting_lines = fitting_quote_lines(&config).filter(|_| config.show_quotes);
+    let quote_filter = QuoteFilter::new(&config, fitting_lines).unwrap_or_else(|e| {
+        eprintln!("Invalid quote filter: {}", e);
+        exit(1);
+    });
+    let quote = get_quote(
+        Path::new(&quote_path),
         &config,
-        &cli,
-        quote_in_lines.len() as u16 + 3 as u16,
+        &quote_filter,
+        image_asset.as_ref(),
+        &mut history,
+        &mut rng,
     );
+    timings.lap("select_quote");
+    match fixed_seed {
+        Some(_) => history.save_last(),
+        None => history.save(),
+    }
+    let quote_in_lines = quote_lines(quote.text(), QUOTE_BOX_WIDTH);
+
+    // e.g. a wide panorama is cropped to the space next to the quote, around its focal point
+    let shown_image = match image_area_ratio(&config) {
+        Some(ratio) if config.crop_to_fit.unwrap_or(false) && !image.is_empty() => {
+            let focus = image_asset.as_ref().and_then(|a| a.sidecar.focus);
+            cache::cropped(Path::new(&image), ratio, focus, &config)
+                .to_string_lossy()
+                .into_owned()
+        }
+        _ => image.clone(),
+    };
+    timings.lap("crop");
+
+    let (w, h, x, y, quote_x, quote_y) =
+        calculate_image_layout(&shown_image, &config, quote_in_lines.len() as u16 + 3);
+    timings.lap("layout");
+
+    let quote_width = quote_in_lines.iter().map(|l| l.len()).max().unwrap_or(0) as u32;
+    let mut image_rect = Rect {
+        x: x as u32,
+        y: y as u32,
+        width: w.unwrap_or(0),
+        height: h.unwrap_or(0),
+    };
+    let quote_rect = Rect {
+        x: quote_x as u32,
+        y: quote_y as u32,
+        width: quote_width,
+        height: quote_in_lines.len() as u32 + 3,
+    };
+
+    let quote_info = QuoteInfo {
+        quote: quote.clone(),
+        file: quote.file.clone(),
+    };
+    // the effects of the sidecar file replace those of the config
+    let effects = image_asset
+        .as_ref()
+        .and_then(|a| a.sidecar.effects.clone())
+        .unwrap_or_else(|| config.image_effects.clone());
     if !cli.no_image {
         // build image conf from nerdcli config
         let image_conf = ImageConfig {
@@ -400,36 +699,87 @@ fn main() {
             height: h, // if cli.height.is_some() {cli.height} else {config.height}, // TODO calculate either width or height
             x: x as u16, // if cli.left.is_some() {cli.left.unwrap()} else {config.margin_left.unwrap()},
             y: y as i16, //if cli.above.is_some() {cli.above.unwrap()} else {config.margin_top.unwrap()},
+            // show the terminal background instead of a checkerboard outside of rounded corners
+            transparent: effects.contains(&ImageEffect::RoundedCorners),
             ..Default::default()
         };
 
         print_quote(quote_in_lines.clone(), quote, quote_x, quote_y, &config);
 
-        print_from_file(image.clone(), &image_conf).expect("Image printing failed.");
+        let render_path =
+            cache::thumbnail(Path::new(&shown_image), w, h, detect_protocol(), &config);
+        timings.lap("thumbnail");
+
+        let effect_color = config.effect_color.as_ref().unwrap_or(&config.quote_color);
+        let render_path = cache::with_effects(
+            &render_path,
+            &effects,
+            effect_color,
+            detect_protocol(),
+            &config,
+        );
+        timings.lap("effects");
+
+        let (printed_width, printed_height) =
+            print_from_file(render_path, &image_conf).expect("Image printing failed.");
+        image_rect.width = printed_width;
+        image_rect.height = printed_height;
 
-        let is_column_layout = cli.layout == Some("COL".to_string())
-            || cli.layout == Some("COL_CENTERED".to_string())
-            || config.layout == Some("COL".to_string())
-            || config.layout == Some("COL_CENTERED".to_string());
+        let is_column_layout = matches!(config.layout.as_deref(), Some("COL" | "COL_CENTERED"));
 
         if is_column_layout {
             for _ in 1..=10 + quote_in_lines.len() {
-                println!("");
+                println!();
             }
         }
-        println!("");
+        println!();
     }
+    timings.lap("render");
 
-    if cli.debug {
+    if cli.debug == Some(DebugFormat::Text) {
         println!("*** DEBUG INFORMATION ***");
         println!("\nSelected image: {}", image);
-        print_config(&config, &cli);
+        println!("Seed: {}", seed);
+        print_config(&config);
 
         println!("\nFound the following images: ");
-        for name in allimages {
+        for name in &allimages {
             println!("\t*{}", name);
         }
     }
 
-    println!("");
+    if cli.debug == Some(DebugFormat::Json) {
+        let image_size = dimensions(Path::new(&image)).ok();
+        let report = DebugReport {
+            origins: config
+                .origins
+                .iter()
+                .map(|(k, v)| (k.clone(), v.to_string()))
+                .collect(),
+            profile: config.profile.clone(),
+            seed,
+            terminal: TerminalInfo::detect(),
+            protocol: detect_protocol().to_string(),
+            image: (!image.is_empty()).then(|| ImageInfo {
+                path: image.clone(),
+                width_px: image_size.map(|(w, _)| w),
+                height_px: image_size.map(|(_, h)| h),
+                tags: image_asset.map(|a| a.tags).unwrap_or_default(),
+                cropped: (shown_image != image).then(|| shown_image.clone()),
+                effects,
+            }),
+            images_found: allimages.len(),
+            quote: quote_info,
+            layout: LayoutInfo {
+                layout: config.layout.clone().unwrap_or_default(),
+                image: image_rect,
+                quote: quote_rect,
+            },
+            timings_ms: Default::default(),
+            config,
+        };
+        report.print_json(&timings);
+    }
+
+    println!();
 }
@@ -0,0 +1,225 @@
+use std::{fs, path::Path, process::exit};
+
+use toml_edit::{value, Array, DocumentMut, Item, Table};
+
+use crate::schema::{write_schema_file, SCHEMA_FILE};
+
+/// The version of the config format written by this version of nerdcli.
+/// Increase it together with a new entry in `MIGRATIONS` whenever settings are renamed or their
+/// meaning changes. New optional settings don't need a migration.
+pub const CONFIG_VERSION: i64 = 2;
+
+/// `MIGRATIONS[i]` upgrades a config from version `i` to version `i + 1`.
+const MIGRATIONS: &[fn(&mut DocumentMut)] = &[v0_to_v1, v1_to_v2];
+
+/// Config files without `config_version` are from before the versioning, nothing else changed.
+fn v0_to_v1(_doc: &mut DocumentMut) {}
+
+/// `include_folders` (folder names) is replaced by the glob patterns `image_filter` and
+/// `quote_filter`. A folder name `f` becomes `**/f/**`, which also includes the sub folders of `f`.
+/// Files directly in the content directory were always included, so `*` is added.
+fn v1_to_v2(doc: &mut DocumentMut) {
+    fn migrate_table(table: &mut Table, with_comment: bool) {
+        let Some(folders) = table.remove("include_folders") else {
+            return;
+        };
+        let folders: Vec<String> = folders
+            .as_array()
+            .map(|a| {
+                a.iter()
+                    .filter_map(|f| f.as_str())
+                    .map(|f| format!("**/{}/**", f.trim_matches('/')))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let patterns: Array = if folders.is_empty() {
+            Array::new()
+        } else {
+            std::iter::once("*".to_string()).chain(folders).collect()
+        };
+
+        table.insert("image_filter", value(patterns.clone()));
+        table.insert("quote_filter", value(patterns));
+        if !with_comment {
+            return;
+        }
+        if let Some(mut key) = table.key_mut("image_filter") {
+            key.leaf_decor_mut().set_prefix(
+                "\n# Glob patterns relative to the image_dir / the language folder in the quotes_dir.\n\
+                 # Patterns starting with ! exclude files, e.g. [\"comics/**\", \"!**/nsfw/**\"]\n",
+            );
+        }
+    }
+
+    migrate_table(doc.as_table_mut(), true);
+    if let Some(profiles) = doc.get_mut("profiles").and_then(Item::as_table_mut) {
+        for (_, profile) in profiles.iter_mut() {
+            if let Some(profile) = profile.as_table_mut() {
+                migrate_table(profile, false);
+            }
+        }
+    }
+}
+
+/// Upgrades the config file in place if it was written for an older config version.
+/// The old file is kept as `nerdcli.toml.bak` and the JSON schema next to it is updated.
+pub fn migrate_config_file(path: &Path) {
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        // reporting a missing file is up to the caller
+        Err(_) => return,
+    };
+    let mut doc = match contents.parse::<DocumentMut>() {
+        Ok(d) => d,
+        // reporting syntax errors is up to the caller
+        Err(_) => return,
+    };
+
+    let version = match doc.get("config_version").map(|v| v.as_integer()) {
+        None => 0,
+        Some(Some(v)) => v,
+        Some(None) => {
+            eprintln!("`config_version` in `{}` must be a number", path.display());
+            exit(1);
+        }
+    };
+    if version == CONFIG_VERSION {
+        return;
+    }
+    if !(0..=CONFIG_VERSION).contains(&version) {
+        eprintln!(
+            "The config file `{}` has version {}, but this nerdcli only supports up to version {}. Please update nerdcli.",
+            path.display(),
+            version,
+            CONFIG_VERSION
+        );
+        exit(1);
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut doc);
+    }
+
+    let migrated = if doc.contains_key("config_version") {
+        doc["config_version"] = value(CONFIG_VERSION);
+        doc.to_string()
+    } else {
+        format!(
+            "#:schema ./{}\n# The version of the config format. Older config files are migrated automatically, do not change it.\nconfig_version = {}\n\n{}",
+            SCHEMA_FILE,
+            CONFIG_VERSION,
+            doc
+        )
+    };
+
+    let backup = path.with_extension("toml.bak");
+    if let Err(e) = fs::copy(path, &backup) {
+        eprintln!(
+            "Could not back up the config file before migrating it to `{}`: {}",
+            backup.display(),
+            e
+        );
+        exit(1);
+    }
+    if let Err(e) = fs::write(path, migrated) {
+        eprintln!("Could not write the migrated config file: {}", e);
+        exit(1);
+    }
+    if let Some(dir) = path.parent() {
+        write_schema_file(dir);
+    }
+
+    eprintln!(
+        "Migrated the config file from version {} to {}. The old file was saved as `{}`.",
+        version,
+        CONFIG_VERSION,
+        backup.display()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config file in a temporary folder of its own.
+    fn config_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("nerdcli-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("nerdcli.toml");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn adds_the_version_to_old_config_files() {
+        let path = config_file("unversioned", "layout = \"ROW\"\n");
+        migrate_config_file(&path);
+
+        let migrated = fs::read_to_string(&path).unwrap();
+        assert!(migrated.contains(&format!("\nconfig_version = {}\n", CONFIG_VERSION)));
+        assert!(migrated.ends_with("layout = \"ROW\"\n"));
+        let dir = path.parent().unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("nerdcli.toml.bak")).unwrap(),
+            "layout = \"ROW\"\n"
+        );
+        assert!(dir.join(SCHEMA_FILE).is_file());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_current_config_files() {
+        let contents = format!("config_version = {}\nlayout = \"ROW\"\n", CONFIG_VERSION);
+        let path = config_file("current", &contents);
+        migrate_config_file(&path);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        assert!(!path.with_extension("toml.bak").exists());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    fn migrated(contents: &str) -> DocumentMut {
+        let mut doc = contents.parse::<DocumentMut>().unwrap();
+        v1_to_v2(&mut doc);
+        doc
+    }
+
+    fn patterns(item: &Item) -> Vec<&str> {
+        item.as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p.as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn replaces_include_folders_with_filters() {
+        let doc = migrated("include_folders = [\"comics\", \"/scifi/\"]\n");
+        assert!(!doc.contains_key("include_folders"));
+        assert_eq!(
+            patterns(&doc["image_filter"]),
+            ["*", "**/comics/**", "**/scifi/**"]
+        );
+        assert_eq!(
+            patterns(&doc["quote_filter"]),
+            patterns(&doc["image_filter"])
+        );
+        assert!(doc.to_string().contains("# Glob patterns"));
+    }
+
+    #[test]
+    fn includes_everything_for_empty_include_folders() {
+        let doc = migrated("include_folders = []\n");
+        assert!(patterns(&doc["image_filter"]).is_empty());
+    }
+
+    #[test]
+    fn migrates_profiles() {
+        let doc = migrated("layout = \"ROW\"\n\n[profiles.work]\ninclude_folders = [\"work\"]\n");
+        assert!(!doc.contains_key("image_filter"));
+        assert_eq!(
+            patterns(&doc["profiles"]["work"]["quote_filter"]),
+            ["*", "**/work/**"]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::{env, process::exit};
+
+use globset::Glob;
+use toml::{Table, Value};
+
+/// Name of the environment variable to select a profile.
+pub const PROFILE_VAR: &str = "NERDCLI_PROFILE";
+
+/// Removes the `[profiles.*]` tables from the config table and returns the name and the settings
+/// of the active profile (if any).
+///
+/// The profile is selected by (in this order):
+/// 1. the `--profile` argument
+/// 2. the `NERDCLI_PROFILE` environment variable
+/// 3. the first profile (in order of the config file) whose `when` rules all match
+pub fn take_profile(table: &mut Table, cli_profile: Option<&String>) -> Option<(String, Table)> {
+    let mut profiles = match table.remove("profiles") {
+        Some(Value::Table(p)) => p,
+        Some(_) => {
+            eprintln!("`profiles` must be a table, e.g. [profiles.work]");
+            exit(1);
+        }
+        None => Table::new(),
+    };
+
+    let requested = cli_profile.cloned().or(env::var(PROFILE_VAR).ok());
+    let name = match requested {
+        Some(name) if name.is_empty() => return None,
+        Some(name) => {
+            if !profiles.contains_key(&name) {
+                eprintln!("Profile `{}` is not defined in the config file.", name);
+                exit(1);
+            }
+            name
+        }
+        None => profiles
+            .iter()
+            .find(|(_, p)| matches_rules(p))
+            .map(|(name, _)| name.clone())?,
+    };
+
+    match profiles.remove(&name) {
+        Some(Value::Table(mut profile)) => {
+            profile.remove("when");
+            Some((name, profile))
+        }
+        _ => {
+            eprintln!(
+                "Profile `{}` must be a table, e.g. [profiles.{}]",
+                name, name
+            );
+            exit(1);
+        }
+    }
+}
+
+/// Removes the `[profiles.*]` tables from the config table and returns the settings of all of them.
+pub fn take_all_profiles(table: &mut Table) -> Result<Vec<(String, Table)>, String> {
+    let profiles = match table.remove("profiles") {
+        Some(Value::Table(p)) => p,
+        Some(_) => return Err("`profiles` must be a table, e.g. [profiles.work]".to_string()),
+        None => return Ok(Vec::new()),
+    };
+
+    profiles
+        .into_iter()
+        .map(|(name, profile)| match profile {
+            Value::Table(mut p) => {
+                p.remove("when");
+                Ok((name, p))
+            }
+            _ => Err(format!("profile `{}` must be a table", name)),
+        })
+        .collect()
+}
+
+/// A profile without `when` rules is never selected automatically.
+/// Each rule is a glob pattern (or a list of patterns, of which one must match).
+fn matches_rules(profile: &Value) -> bool {
+    let rules = match profile.get("when").and_then(|w| w.as_table()) {
+        Some(r) if !r.is_empty() => r,
+        _ => return false,
+    };
+
+    rules.iter().all(|(key, patterns)| {
+        let actual = match key.as_str() {
+            "hostname" => Some(gethostname::gethostname().to_string_lossy().into_owned()),
+            "term_program" => env::var("TERM_PROGRAM").ok(),
+            "cwd" => env::current_dir()
+                .ok()
+                .map(|d| d.to_string_lossy().into_owned()),
+            _ => {
+                eprintln!(
+                    "Unknown profile rule `{}` (use hostname, term_program or cwd)",
+                    key
+                );
+                None
+            }
+        };
+        let Some(actual) = actual else {
+            return false;
+        };
+
+        let patterns: Vec<&str> = match patterns {
+            Value::String(p) => vec![p.as_str()],
+            Value::Array(a) => a.iter().filter_map(|p| p.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        patterns.iter().any(|p| {
+            let pattern = if key == "cwd" {
+                expand_home(p)
+            } else {
+                p.to_string()
+            };
+            match Glob::new(&pattern) {
+                Ok(glob) => glob.compile_matcher().is_match(&actual),
+                Err(e) => {
+                    eprintln!("Invalid pattern `{}` in profile rule: {}", p, e);
+                    false
+                }
+            }
+        })
+    })
+}
+
+fn expand_home(pattern: &str) -> String {
+    match (pattern.strip_prefix("~"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => format!("{}{}", home.to_string_lossy(), rest),
+        _ => pattern.to_string(),
+    }
+}
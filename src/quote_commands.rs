@@ -0,0 +1,576 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeSet,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf, MAIN_SEPARATOR_STR},
+    process::exit,
+};
+
+use clap::{Args, Subcommand, ValueEnum};
+use serde_derive::Serialize;
+use toml_edit::{ArrayOfTables, DocumentMut, Item};
+
+use crate::filters::{self, PathFilter};
+use crate::quote_filter::QuoteFilter;
+use crate::quote_formats::QuoteFormat;
+use crate::quotes::{file_tags, language_folders, parse_quotes, read_quotes, Quote, Text};
+use crate::selection::relative_path;
+use crate::settings::{parse_config, NerdcliConfig};
+use crate::tags::normalize;
+use crate::Cli;
+
+/// The quote file in the language folder that `quote add` appends to, if no --file is given.
+const DEFAULT_QUOTE_FILE: &str = "my-quotes.toml";
+
+#[derive(Subcommand)]
+pub enum QuoteCommand {
+    #[command(about = "Add a quote to a toml quote file (comments are preserved)")]
+    Add(Box<AddArgs>),
+
+    #[command(
+        about = "Import the quotes of a yaml, json, jsonl, csv, fortune or toml file into a toml quote file, skipping duplicates"
+    )]
+    Import {
+        #[arg(help = "The file to import")]
+        path: PathBuf,
+
+        #[command(flatten)]
+        target: TargetFile,
+    },
+
+    #[command(
+        about = "List the quotes of all languages, filtered by --author, --tag, --source, --grep and --max-lines"
+    )]
+    List {
+        #[arg(short, long, value_enum, default_value_t = ListFormat::Table)]
+        format: ListFormat,
+    },
+
+    #[command(about = "Search quotes by their text and author, the best matches first")]
+    Search {
+        #[arg(help = "The words to search for, missing letters are tolerated (e.g. hitchhkr)")]
+        query: String,
+
+        #[arg(
+            short = 'n',
+            long,
+            default_value_t = 10,
+            help = "The number of results"
+        )]
+        limit: usize,
+
+        #[arg(short, long, value_enum, default_value_t = ListFormat::Table)]
+        format: ListFormat,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ListFormat {
+    /// Text, author and file of every quote, one per line.
+    Table,
+    Json,
+    /// The BSD fortune format, e.g. to be used with `fortune`.
+    Fortune,
+}
+
+#[derive(Args)]
+pub struct TargetFile {
+    #[arg(
+        short,
+        long,
+        help = "The toml file in the language folder to write to, e.g. sf.toml (default: my-quotes.toml, the name of the imported file for imports)"
+    )]
+    file: Option<String>,
+
+    #[arg(
+        long,
+        help = "The language folder in the quotes_dir (default: the first of the quote_languages)"
+    )]
+    language: Option<String>,
+}
+
+#[derive(Args)]
+pub struct AddArgs {
+    #[arg(long, help = "The text of the quote")]
+    text: String,
+
+    // --author, --source and --tag replace the global quote filters of the same name here, which
+    // are not applied when adding a quote
+    #[arg(long, help = "Who wrote or said the quote")]
+    author: String,
+
+    #[arg(long, help = "The book, speech or website the quote is from")]
+    source: Option<String>,
+
+    #[arg(
+        long,
+        alias = "tags",
+        help = "A tag of the quote (can be given more than once)"
+    )]
+    tag: Vec<String>,
+
+    #[arg(
+        long,
+        help = "The language of the quote, if it differs from the language folder it is written to"
+    )]
+    lang: Option<String>,
+
+    #[arg(long, help = "The date of the quote, e.g. 1984")]
+    date: Option<String>,
+
+    #[arg(
+        long,
+        help = "A unique name to reference the quote, e.g. from an image"
+    )]
+    id: Option<String>,
+
+    #[arg(long, help = "Who says it in the fiction (while --author wrote it)")]
+    character: Option<String>,
+
+    #[arg(long, help = "The book, film or series the quote is from")]
+    work: Option<String>,
+
+    #[arg(long, help = "The year of the work")]
+    year: Option<i32>,
+
+    #[arg(long, help = "Where the quote comes from")]
+    url: Option<String>,
+
+    #[arg(long, help = "The quote is not safe for work")]
+    nsfw: bool,
+
+    #[command(flatten)]
+    target: TargetFile,
+}
+
+pub fn run(command: &QuoteCommand, cli: &Cli) {
+    let config = parse_config(cli);
+    match command {
+        QuoteCommand::Add(args) => add(args, &config),
+        QuoteCommand::Import { path, target } => import(path, target, &config),
+        QuoteCommand::List { format } => list(*format, &config),
+        QuoteCommand::Search {
+            query,
+            limit,
+            format,
+        } => search(query, *limit, *format, &config),
+    }
+}
+
+/// A quote of the collection with the tags of its file and the path of its file.
+struct Stored {
+    quote: Quote,
+    tags: BTreeSet<String>,
+    /// Relative to the quotes_dir, e.g. `en/science-fiction.toml`.
+    file: String,
+}
+
+#[derive(Serialize)]
+struct Listed<'a> {
+    #[serde(flatten)]
+    quote: &'a Quote,
+    file: &'a str,
+}
+
+/// All quotes in the language folders of the quotes_dir, including the files excluded by the
+/// quote_filter.
+fn collection(config: &NerdcliConfig) -> Vec<Stored> {
+    let dir = quotes_dir(config);
+    let accept = |p: &Path| QuoteFormat::of(p).is_some();
+    let mut stored = Vec::new();
+    for language in language_folders(&dir) {
+        let root = dir.join(&language);
+        let files =
+            filters::list_files(&root, &PathFilter::new(&[]).unwrap(), &accept).unwrap_or_default();
+        for file in files {
+            let tags = file_tags(&root, &file);
+            let relative = relative_path(&dir, &file);
+            for mut quote in parse_quotes(&file).quotes {
+                // translated quotes are shown in their lang or the language of their folder
+                quote.language = Some(quote.lang.clone().unwrap_or(language.clone()));
+                let mut tags = tags.clone();
+                tags.extend(quote.tags.iter().map(|t| normalize(t)));
+                stored.push(Stored {
+                    quote,
+                    tags,
+                    file: relative.clone(),
+                });
+            }
+        }
+    }
+    stored
+}
+
+fn list(format: ListFormat, config: &NerdcliConfig) {
+    let filter = QuoteFilter::new(config, None).unwrap_or_else(|e| {
+        eprintln!("Invalid quote filter: {}", e);
+        exit(1);
+    });
+    let quotes: Vec<Stored> = collection(config)
+        .into_iter()
+        .filter(|q| filter.matches(&q.quote, &q.tags))
+        .collect();
+    print_quotes(&quotes.iter().collect::<Vec<_>>(), format);
+}
+
+fn search(query: &str, limit: usize, format: ListFormat, config: &NerdcliConfig) {
+    let words: Vec<String> = query.split_whitespace().map(normalized).collect();
+    let quotes = collection(config);
+    let mut ranked: Vec<(u32, &Stored)> = quotes
+        .iter()
+        .filter_map(|q| {
+            let text = normalized_words(q.quote.text());
+            let author = normalized_words(&q.quote.author);
+            // every word must be found in the text or the author
+            let mut score = 0;
+            for word in &words {
+                score += [fuzzy_score(word, &text), fuzzy_score(word, &author)]
+                    .into_iter()
+                    .flatten()
+                    .max()?;
+            }
+            Some((score, q))
+        })
+        .collect();
+    ranked.sort_by_key(|(score, _)| Reverse(*score));
+    let results: Vec<&Stored> = ranked.into_iter().take(limit).map(|(_, q)| q).collect();
+    if results.is_empty() {
+        eprintln!("No quotes found for `{}`", query);
+        exit(1);
+    }
+    print_quotes(&results, format);
+}
+
+fn normalized_words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(normalized)
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+/// Scores how well a word matches one of the words of a text: an exact word scores highest, then
+/// the start of a word, then the letters of the word in order (e.g. `hitchhkr` in `hitchhiker`).
+/// None, if the letters are not found in any word.
+fn fuzzy_score(needle: &str, words: &[String]) -> Option<u32> {
+    let len = needle.chars().count() as u32;
+    words
+        .iter()
+        .filter_map(|word| {
+            if word == needle {
+                return Some(4 * len);
+            }
+            if word.starts_with(needle) {
+                return Some(3 * len);
+            }
+            if word.contains(needle) {
+                return Some(2 * len);
+            }
+            // the letters in order, consecutive letters score more
+            let mut score = 0;
+            let mut chars = word.chars();
+            for (i, n) in needle.chars().enumerate() {
+                let mut skipped = false;
+                loop {
+                    match chars.next() {
+                        Some(c) if c == n => break,
+                        Some(_) => skipped = true,
+                        None => return None,
+                    }
+                }
+                score += if i > 0 && !skipped { 2 } else { 1 };
+            }
+            // at most half of the word may be missing
+            (len * 2 >= word.chars().count() as u32).then_some(score)
+        })
+        .max()
+}
+
+fn print_quotes(quotes: &[&Stored], format: ListFormat) {
+    match write_quotes(&mut io::stdout().lock(), quotes, format) {
+        // e.g. `nerdcli quote list | head`
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            eprintln!("Unable to print the quotes: {}", e);
+            exit(1);
+        }
+        Ok(_) => {}
+    }
+}
+
+fn write_quotes(out: &mut impl Write, quotes: &[&Stored], format: ListFormat) -> io::Result<()> {
+    match format {
+        ListFormat::Table => {
+            let texts: Vec<String> = quotes
+                .iter()
+                .map(|q| shortened(&q.quote.text().replace('\n', " "), 60))
+                .collect();
+            let authors: Vec<String> = quotes
+                .iter()
+                .map(|q| shortened(&q.quote.author, 30))
+                .collect();
+            let text_width = texts.iter().map(|t| t.chars().count()).max().unwrap_or(0);
+            let author_width = authors.iter().map(|a| a.chars().count()).max().unwrap_or(0);
+            for ((text, author), quote) in texts.iter().zip(&authors).zip(quotes) {
+                writeln!(
+                    out,
+                    "{:text_width$}  {:author_width$}  {}",
+                    text, author, quote.file
+                )?;
+            }
+        }
+        ListFormat::Json => {
+            let listed: Vec<Listed> = quotes
+                .iter()
+                .map(|q| Listed {
+                    quote: &q.quote,
+                    file: &q.file,
+                })
+                .collect();
+            let json = serde_json::to_string_pretty(&listed).map_err(io::Error::other)?;
+            writeln!(out, "{}", json)?;
+        }
+        ListFormat::Fortune => {
+            for quote in quotes {
+                writeln!(out, "{}", quote.quote.text())?;
+                if !quote.quote.author.is_empty() {
+                    writeln!(out, "\t\t-- {}", quote.quote.author)?;
+                }
+                writeln!(out, "%")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Cuts the text after `max` characters.
+fn shortened(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut short: String = text.chars().take(max - 1).collect();
+    short.push('…');
+    short
+}
+
+fn add(args: &AddArgs, config: &NerdcliConfig) {
+    let quote = Quote {
+        text: args.text.as_str().into(),
+        author: args.author.clone(),
+        source: args.source.clone(),
+        date: args.date.clone(),
+        id: args.id.clone(),
+        character: args.character.clone(),
+        work: args.work.clone(),
+        year: args.year,
+        url: args.url.clone(),
+        nsfw: args.nsfw,
+        tags: args.tag.clone(),
+        lang: args.lang.clone(),
+        ..Default::default()
+    };
+
+    let mut known = known_texts(config);
+    if is_duplicate(&quote, &mut known) {
+        eprintln!("The quote is already in the collection, nothing was changed.");
+        exit(1);
+    }
+    let target = target_path(&args.target, DEFAULT_QUOTE_FILE, config);
+    append_quotes(&target, &[quote]);
+    println!("Added the quote to `{}`.", target.display());
+}
+
+fn import(path: &Path, target: &TargetFile, config: &NerdcliConfig) {
+    if QuoteFormat::of(path).is_none() {
+        eprintln!(
+            "`{}` is not a quote file (yaml, json, jsonl, csv, fortune or toml)",
+            path.display()
+        );
+        exit(1);
+    }
+    let quotes = read_quotes(path).unwrap_or_else(|e| {
+        eprintln!("Unable to load quote data from `{}`: {}", path.display(), e);
+        exit(1);
+    });
+    let default_file = format!(
+        "{}.toml",
+        path.file_stem().unwrap_or_default().to_string_lossy()
+    );
+    let target = target_path(target, &default_file, config);
+
+    // the file itself may already be part of the collection
+    let mut known = known_texts(config);
+    let total = quotes.len();
+    let new: Vec<Quote> = quotes
+        .into_iter()
+        .filter(|quote| !is_duplicate(quote, &mut known))
+        .collect();
+    if !new.is_empty() {
+        append_quotes(&target, &new);
+    }
+    println!(
+        "Imported {} quotes to `{}`, skipped {} duplicates.",
+        new.len(),
+        target.display(),
+        total - new.len()
+    );
+}
+
+fn target_path(target: &TargetFile, default_file: &str, config: &NerdcliConfig) -> PathBuf {
+    let file = target.file.as_deref().unwrap_or(default_file);
+    if QuoteFormat::of(Path::new(file)) != Some(QuoteFormat::Toml) {
+        eprintln!(
+            "Quotes can only be written to toml files, not to `{}`",
+            file
+        );
+        exit(1);
+    }
+    let language = target
+        .language
+        .clone()
+        .unwrap_or_else(|| config.quote_languages.weighted()[0].0.clone());
+    quotes_dir(config).join(language).join(file)
+}
+
+fn quotes_dir(config: &NerdcliConfig) -> PathBuf {
+    PathBuf::from(
+        [
+            config.config_base_path.clone().unwrap(),
+            config.quotes_dir.clone(),
+        ]
+        .join(MAIN_SEPARATOR_STR),
+    )
+}
+
+/// Quotes are the same if their texts only differ in case, whitespace and punctuation.
+fn normalized(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The normalized texts (and translations) of all quotes in the collection.
+fn known_texts(config: &NerdcliConfig) -> BTreeSet<String> {
+    collection(config)
+        .iter()
+        .flat_map(|stored| texts(&stored.quote))
+        .collect()
+}
+
+fn texts(quote: &Quote) -> Vec<String> {
+    match &quote.text {
+        Text::Plain(text) => vec![normalized(text)],
+        Text::Translated(texts) => texts.values().map(|t| normalized(t)).collect(),
+    }
+}
+
+/// Checks the quote against the known texts and adds its texts to them.
+fn is_duplicate(quote: &Quote, known: &mut BTreeSet<String>) -> bool {
+    let texts = texts(quote);
+    if texts.iter().any(|t| t.is_empty() || known.contains(t)) {
+        return true;
+    }
+    known.extend(texts);
+    false
+}
+
+/// Appends the quotes to a toml quote file as `[[quotes]]`, keeping its comments and formatting.
+fn append_quotes(path: &Path, quotes: &[Quote]) {
+    let contents = fs::read_to_string(path).unwrap_or_default();
+    let mut doc = match contents.parse::<DocumentMut>() {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("Unable to parse quote file `{}`: {}", path.display(), e);
+            exit(1);
+        }
+    };
+    let Some(list) = doc
+        .entry("quotes")
+        .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+    else {
+        eprintln!("`quotes` is not a list of tables in `{}`", path.display());
+        exit(1);
+    };
+
+    for quote in quotes {
+        match to_table(quote) {
+            Ok(table) => list.push(table),
+            Err(e) => {
+                eprintln!("Unable to write the quote `{}`: {}", quote.text(), e);
+                exit(1);
+            }
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("Could not create the folder `{}`: {}", dir.display(), e);
+            exit(1);
+        }
+    }
+    if let Err(e) = fs::write(path, doc.to_string()) {
+        eprintln!("Could not write quote file `{}`: {}", path.display(), e);
+        exit(1);
+    }
+}
+
+/// The quote as a toml table, without the fields that have their default value. Translations
+/// are written inline, as `text = { en = "...", de = "..." }`.
+fn to_table(quote: &Quote) -> Result<toml_edit::Table, String> {
+    let fields = toml::Table::try_from(quote).map_err(|e| e.to_string())?;
+    let mut table = toml_edit::Table::new();
+    for (key, value) in fields {
+        let is_default = match &value {
+            toml::Value::Boolean(b) => !b,
+            toml::Value::Array(a) => a.is_empty(),
+            _ => false,
+        };
+        if !is_default {
+            let value = value
+                .to_string()
+                .parse::<toml_edit::Value>()
+                .map_err(|e| e.to_string())?;
+            table.insert(&key, Item::Value(value));
+        }
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn scores_exact_words_over_prefixes_and_parts() {
+        let exact = fuzzy_score("towel", &words("a towel"));
+        let prefix = fuzzy_score("towel", &words("towels"));
+        let part = fuzzy_score("towel", &words("dishtowels"));
+        assert!(
+            exact > prefix && prefix > part,
+            "{:?} {:?} {:?}",
+            exact,
+            prefix,
+            part
+        );
+    }
+
+    #[test]
+    fn tolerates_missing_letters() {
+        let score = fuzzy_score("hitchhkr", &words("the hitchhiker's guide"));
+        assert!(score.is_some());
+        assert!(score < fuzzy_score("hitchhik", &words("the hitchhiker's guide")));
+    }
+
+    #[test]
+    fn ignores_words_with_too_few_or_unordered_letters() {
+        assert_eq!(fuzzy_score("ae", &words("abcdefgh")), None);
+        assert_eq!(fuzzy_score("olt", &words("towel")), None);
+        assert_eq!(fuzzy_score("x", &words("")), None);
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeSet;
+
+use regex::Regex;
+
+use crate::quote_layout::{line_count, QUOTE_BOX_WIDTH};
+use crate::quotes::Quote;
+use crate::settings::NerdcliConfig;
+use crate::tags::normalize;
+
+/// Restricts the quotes that can be selected, by the settings quote_author, quote_tags,
+/// quote_source, quote_max_lines and quote_grep (or the program arguments --author, ...).
+pub struct QuoteFilter {
+    author: Option<String>,
+    tags: BTreeSet<String>,
+    source: Option<String>,
+    grep: Option<Regex>,
+    max_lines: Option<usize>,
+    /// The number of lines that fit into the terminal, longer quotes are never selected.
+    fitting_lines: Option<usize>,
+    nsfw: bool,
+}
+
+impl QuoteFilter {
+    pub fn new(
+        config: &NerdcliConfig,
+        fitting_lines: Option<usize>,
+    ) -> Result<QuoteFilter, String> {
+        let grep = match &config.quote_grep {
+            Some(pattern) => {
+                Some(Regex::new(pattern).map_err(|e| format!("quote_grep `{}`: {}", pattern, e))?)
+            }
+            None => None,
+        };
+        Ok(QuoteFilter {
+            author: config.quote_author.as_deref().map(str::to_lowercase),
+            tags: config.quote_tags.iter().map(|t| normalize(t)).collect(),
+            source: config.quote_source.as_deref().map(str::to_lowercase),
+            grep,
+            max_lines: config.quote_max_lines,
+            fitting_lines,
+            nsfw: config.nsfw_quotes,
+        })
+    }
+
+    /// True, if the quotes are filtered by author, tag, source or text.
+    pub fn is_active(&self) -> bool {
+        self.author.is_some()
+            || !self.tags.is_empty()
+            || self.source.is_some()
+            || self.grep.is_some()
+    }
+
+    /// Checks the limits that apply to every quote: the number of lines and nsfw.
+    pub fn allows(&self, quote: &Quote) -> bool {
+        if quote.nsfw && !self.nsfw {
+            return false;
+        }
+        let limit = match (self.max_lines, self.fitting_lines) {
+            (Some(max), Some(fitting)) => Some(max.min(fitting)),
+            (max, fitting) => max.or(fitting),
+        };
+        limit.is_none_or(|limit| line_count(quote.text(), QUOTE_BOX_WIDTH) <= limit)
+    }
+
+    /// Checks all filters. `tags` are the tags of the quote including those of its file.
+    pub fn matches(&self, quote: &Quote, tags: &BTreeSet<String>) -> bool {
+        let contains = |field: Option<&String>, part: &str| {
+            field.is_some_and(|f| f.to_lowercase().contains(part))
+        };
+
+        self.author.as_ref().is_none_or(|a| {
+            contains(Some(&quote.author), a) || contains(quote.character.as_ref(), a)
+        }) && (self.tags.is_empty() || !self.tags.is_disjoint(tags))
+            && self.source.as_ref().is_none_or(|s| {
+                contains(quote.source.as_ref(), s) || contains(quote.work.as_ref(), s)
+            })
+            && self.grep.as_ref().is_none_or(|g| g.is_match(quote.text()))
+            && self.allows(quote)
+    }
+}
@@ -0,0 +1,228 @@
+use std::{fs, path::Path};
+
+use serde_derive::Deserialize;
+
+use crate::quotes::{Quote, Quotes};
+
+/// The file formats quotes can be read from, selected by the extension of the file.
+#[derive(Clone, Copy, PartialEq)]
+pub enum QuoteFormat {
+    Toml,
+    Yaml,
+    Json,
+    /// One quote object per line.
+    JsonLines,
+    /// The columns text, author, source and date.
+    Csv,
+    /// The BSD fortune format: quotes separated by lines with a single `%`.
+    Fortune,
+}
+
+/// A YAML or JSON document: like the toml files an object with a `quotes` list, or just the list.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Document {
+    Quotes(Quotes),
+    List(Vec<Quote>),
+}
+
+impl Document {
+    fn into_quotes(self) -> Vec<Quote> {
+        match self {
+            Document::Quotes(quotes) => quotes.quotes,
+            Document::List(quotes) => quotes,
+        }
+    }
+}
+
+impl QuoteFormat {
+    /// Fortune databases (e.g. in /usr/share/games/fortunes) have no extension. Hidden files like
+    /// `.nerdcli-weight` and the `.dat` index files of fortune are no quote files.
+    pub fn of(path: &Path) -> Option<QuoteFormat> {
+        let name = path.file_name()?.to_string_lossy();
+        if name.starts_with('.') {
+            return None;
+        }
+        let extension = match path.extension() {
+            Some(extension) => extension.to_string_lossy().to_lowercase(),
+            None => return is_fortune_database(path).then_some(QuoteFormat::Fortune),
+        };
+        match extension.as_str() {
+            "toml" => Some(QuoteFormat::Toml),
+            "yaml" | "yml" => Some(QuoteFormat::Yaml),
+            "json" => Some(QuoteFormat::Json),
+            "jsonl" => Some(QuoteFormat::JsonLines),
+            "csv" => Some(QuoteFormat::Csv),
+            "fortune" => Some(QuoteFormat::Fortune),
+            _ => None,
+        }
+    }
+
+    pub fn parse(self, contents: &str) -> Result<Vec<Quote>, String> {
+        match self {
+            QuoteFormat::Toml => toml::from_str::<Quotes>(contents)
+                .map(|q| q.quotes)
+                .map_err(|e| e.to_string()),
+            QuoteFormat::Yaml => serde_yaml::from_str::<Document>(contents)
+                .map(Document::into_quotes)
+                .map_err(|e| e.to_string()),
+            QuoteFormat::Json => serde_json::from_str::<Document>(contents)
+                .map(Document::into_quotes)
+                .map_err(|e| e.to_string()),
+            QuoteFormat::JsonLines => contents
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(i, line)| {
+                    serde_json::from_str(line).map_err(|e| format!("line {}: {}", i + 1, e))
+                })
+                .collect(),
+            QuoteFormat::Csv => parse_csv(contents),
+            QuoteFormat::Fortune => Ok(parse_fortune(contents)),
+        }
+    }
+}
+
+/// Files without extension like README or LICENSE are no quotes: a fortune database has a `.dat`
+/// index file next to it (created by strfile) or at least a line with a single `%`.
+fn is_fortune_database(path: &Path) -> bool {
+    path.with_extension("dat").is_file()
+        || fs::read(path).is_ok_and(|bytes| {
+            String::from_utf8_lossy(&bytes)
+                .lines()
+                .any(|line| line.trim_end() == "%")
+        })
+}
+
+/// Reads the columns text, author, source and date (the last two are optional). A header line
+/// starting with `text` is skipped.
+fn parse_csv(contents: &str) -> Result<Vec<Quote>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    let mut quotes = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| e.to_string())?;
+        let field = |n: usize| {
+            record
+                .get(n)
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+        };
+        let Some(text) = field(0) else {
+            continue;
+        };
+        if i == 0 && text.eq_ignore_ascii_case("text") {
+            continue;
+        }
+        quotes.push(Quote {
+            text: text.as_str().into(),
+            author: field(1).unwrap_or_default(),
+            source: field(2),
+            date: field(3),
+            ..Default::default()
+        });
+    }
+    Ok(quotes)
+}
+
+/// Reads quotes separated by lines with a single `%`. A last line starting with `--` is the
+/// author. The hard line breaks of a paragraph are removed, so the quote fits into the quote box.
+fn parse_fortune(contents: &str) -> Vec<Quote> {
+    let mut quotes = Vec::new();
+    let mut entry: Vec<&str> = Vec::new();
+    for line in contents.lines().chain(std::iter::once("%")) {
+        if line.trim_end() != "%" {
+            entry.push(line.trim_end());
+            continue;
+        }
+
+        while entry.last().is_some_and(|l| l.trim().is_empty()) {
+            entry.pop();
+        }
+        let author = match entry.last().map(|l| l.trim()) {
+            Some(last) if last.starts_with("--") || last.starts_with('—') => {
+                let author = last.trim_start_matches(['-', '—']).trim().to_string();
+                entry.pop();
+                author
+            }
+            _ => String::new(),
+        };
+        let paragraphs: Vec<String> = entry
+            .split(|l| l.trim().is_empty())
+            .filter(|p| !p.is_empty())
+            .map(|p| p.iter().map(|l| l.trim()).collect::<Vec<_>>().join(" "))
+            .collect();
+        if !paragraphs.is_empty() {
+            quotes.push(Quote {
+                text: paragraphs.join("\n").as_str().into(),
+                author,
+                ..Default::default()
+            });
+        }
+        entry.clear();
+    }
+    quotes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_the_format_by_extension() {
+        assert!(QuoteFormat::of(Path::new("en/scifi.toml")) == Some(QuoteFormat::Toml));
+        assert!(QuoteFormat::of(Path::new("en/scifi.YML")) == Some(QuoteFormat::Yaml));
+        assert!(QuoteFormat::of(Path::new("en/scifi.fortune")) == Some(QuoteFormat::Fortune));
+        assert!(QuoteFormat::of(Path::new("en/.nerdcli-weight")).is_none());
+        assert!(QuoteFormat::of(Path::new("en/fortunes.dat")).is_none());
+    }
+
+    #[test]
+    fn reads_files_without_extension_only_if_they_are_fortune_databases() {
+        let dir = std::env::temp_dir().join(format!("nerdcli-formats-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("README"), "Some text\n").unwrap();
+        fs::write(dir.join("fortunes"), "One.\n%\nTwo.\n").unwrap();
+        fs::write(dir.join("single"), "Only one.\n").unwrap();
+        fs::write(dir.join("single.dat"), "").unwrap();
+
+        assert!(QuoteFormat::of(&dir.join("README")).is_none());
+        assert!(QuoteFormat::of(&dir.join("fortunes")) == Some(QuoteFormat::Fortune));
+        assert!(QuoteFormat::of(&dir.join("single")) == Some(QuoteFormat::Fortune));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parses_fortune_entries_with_authors() {
+        let quotes = parse_fortune(
+            "Don't panic.\n-- Douglas Adams\n%\nFirst line\nsecond line.\n\nNext paragraph.\n%\n",
+        );
+        assert_eq!(quotes.len(), 2);
+        assert_eq!(quotes[0].text(), "Don't panic.");
+        assert_eq!(quotes[0].author, "Douglas Adams");
+        assert_eq!(quotes[1].text(), "First line second line.\nNext paragraph.");
+        assert_eq!(quotes[1].author, "");
+    }
+
+    #[test]
+    fn parses_csv_with_and_without_header() {
+        let quotes = parse_csv("text,author,source\n\"Hello, world\",Me,Blog\nBye,You\n").unwrap();
+        assert_eq!(quotes.len(), 2);
+        assert_eq!(quotes[0].text(), "Hello, world");
+        assert_eq!(quotes[0].source.as_deref(), Some("Blog"));
+        assert_eq!(quotes[1].author, "You");
+        assert_eq!(parse_csv("Hi,Me\n").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reports_the_line_of_broken_json_lines() {
+        let error = QuoteFormat::JsonLines
+            .parse("{\"text\": \"a\", \"author\": \"b\"}\n{broken\n")
+            .err()
+            .unwrap();
+        assert!(error.starts_with("line 2"));
+    }
+}
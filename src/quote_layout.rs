@@ -0,0 +1,69 @@
+/// The width of the quote box in terminal cells.
+pub const QUOTE_BOX_WIDTH: u16 = 50;
+
+/// Splits the text into lines only at word breaks and only if a line is longer than `width`.
+/// Every word is followed by a space.
+pub fn quote_lines(text: &str, width: u16) -> Vec<String> {
+    let mut lines = Vec::new();
+    wrap(text, width, |words| {
+        lines.push(words.iter().map(|w| format!("{} ", w)).collect())
+    });
+    lines
+}
+
+/// The number of lines of `quote_lines`, without building them.
+pub fn line_count(text: &str, width: u16) -> usize {
+    let mut count = 0;
+    wrap(text, width, |_| count += 1);
+    count
+}
+
+/// Calls `line` with the words of every line.
+fn wrap<'a>(text: &'a str, width: u16, mut line: impl FnMut(&[&'a str])) {
+    let mut words = Vec::new();
+    let mut length = 0;
+    for natural_line in text.split('\n') {
+        for word in natural_line.split_whitespace() {
+            if length + word.len() > width as usize {
+                line(&words);
+                words.clear();
+                length = 0;
+            }
+            words.push(word);
+            length += word.len() + 1;
+        }
+        line(&words);
+        words.clear();
+        length = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_at_word_breaks() {
+        assert_eq!(
+            quote_lines("Don't panic, and take a towel.", 12),
+            ["Don't panic, ", "and take a ", "towel. "]
+        );
+    }
+
+    #[test]
+    fn keeps_line_breaks_of_the_text() {
+        assert_eq!(quote_lines("One.\n\nTwo.", 50), ["One. ", "", "Two. "]);
+    }
+
+    #[test]
+    fn counts_the_wrapped_lines() {
+        for text in [
+            "",
+            "short",
+            "Don't panic, and take a towel.",
+            "a\nb c d e f g h",
+        ] {
+            assert_eq!(line_count(text, 5), quote_lines(text, 5).len());
+        }
+    }
+}
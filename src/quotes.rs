@@ -1,12 +1,20 @@
 use std::{
-    fs::{self, metadata},
-    io,
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
     path::{Path, PathBuf},
 };
 
+use crate::content::ImageAsset;
+use crate::filters::{self, PathFilter};
+use crate::history::History;
+use crate::index::fnv_hash;
+use crate::quote_filter::QuoteFilter;
+use crate::quote_formats::QuoteFormat;
+use crate::ratings::{Rating, Ratings};
+use crate::selection::{relative_path, Candidates, SelectionMode, Weights};
 use crate::settings::NerdcliConfig;
-use serde_derive::Deserialize;
-use toml;
+use crate::tags::{folder_tags, normalize};
+use serde_derive::{Deserialize, Serialize};
 
 use rand::prelude::*;
 
@@ -15,118 +23,393 @@ pub struct Quotes {
     pub quotes: Vec<Quote>,
 }
 
-#[derive(Deserialize, Default, Clone)]
+/// The text of a quote: a string or its translations, e.g. `text.en = "..."` and `text.de = "..."`,
+/// so one quote file serves multiple languages.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum Text {
+    Plain(String),
+    Translated(BTreeMap<String, String>),
+}
+
+impl Default for Text {
+    fn default() -> Self {
+        Text::Plain(String::new())
+    }
+}
+
+impl From<&str> for Text {
+    fn from(text: &str) -> Self {
+        Text::Plain(text.to_string())
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Clone)]
 pub struct Quote {
-    pub text: String,
+    pub text: Text,
     pub author: String,
     pub source: Option<String>,
     pub date: Option<String>,
+    /// Referenced by the `quote_ids` of an image sidecar file.
+    pub id: Option<String>,
+    /// Who says it in the fiction, e.g. `Roy Batty` (while the author wrote it).
+    pub character: Option<String>,
+    /// The book, film or series the quote is from.
+    pub work: Option<String>,
+    pub year: Option<i32>,
+    pub url: Option<String>,
+    /// The language of the quote, e.g. `en`, if it differs from the language of its folder.
+    pub lang: Option<String>,
+    /// Quotes that are not safe for work are only shown with `nsfw_quotes = true`.
+    #[serde(default)]
+    pub nsfw: bool,
+    /// Quotes with a tag of the shown image are preferred, e.g. `tags = ["hellboy"]`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Glob patterns of images (relative to the image_dir) this quote is always shown with.
+    #[serde(default)]
+    pub images: Vec<String>,
+    /// The file the quote was read from.
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+    /// The language the quote is shown in, selects one of the translations.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
 }
 
-#[derive(Deserialize, Default, Clone)]
+impl Quote {
+    /// Identifies a quote in the history, independent of the file it is stored in.
+    pub fn key(&self) -> String {
+        let text = match &self.text {
+            Text::Plain(text) => text,
+            // the same for all translations
+            Text::Translated(texts) => texts.values().next().map_or("", String::as_str),
+        };
+        fnv_hash(format!("{}|{}", text, self.author).as_bytes())
+    }
+
+    /// The text in the language the quote is shown in, or the first translation if there is none.
+    pub fn text(&self) -> &str {
+        match &self.text {
+            Text::Plain(text) => text,
+            Text::Translated(texts) => self
+                .language
+                .as_ref()
+                .and_then(|l| texts.get(l))
+                .or_else(|| texts.values().next())
+                .map_or("", String::as_str),
+        }
+    }
+
+    fn has_translation(&self, language: &str) -> bool {
+        matches!(&self.text, Text::Translated(texts) if texts.contains_key(language))
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Clone)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
 }
 
-fn _list_files(vec: &mut Vec<PathBuf>, path: &Path, config: &NerdcliConfig) -> io::Result<()> {
-    if metadata(&path)?.is_dir() {
-        let paths = fs::read_dir(&path)?;
-        for path_result in paths {
-            let full_path = path_result?.path();
-            if metadata(&full_path)?.is_dir() {
-                if config.include_folders.is_empty()
-                    || config
-                        .include_folders
-                        .iter()
-                        .any(|f| full_path.ends_with(f))
-                {
-                    _list_files(vec, &full_path, config)?
-                } else {
-                }
-            } else {
-                if let Some(ext) = full_path.extension().and_then(|ext| ext.to_str()) {
-                    if ext.to_string() == "toml" {
-                        vec.push(full_path);
+/// Lists all quote files in `path` that are selected by the `quote_filter`, with the weights of
+/// their folders.
+pub fn list_files(path: &Path, config: &NerdcliConfig) -> io::Result<Candidates> {
+    let filter = PathFilter::new(&config.quote_filter)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("quote_filter: {}", e)))?;
+    let files = filters::list_files(path, &filter, &|p| QuoteFormat::of(p).is_some())?;
+    let folder_weights = Weights::read_weight_files(path, &files);
+    Ok(Candidates {
+        root: path.to_path_buf(),
+        files,
+        weights: Weights::new(&config.quote_weights, folder_weights),
+    })
+}
+
+/// The language folders in the quotes_dir `path`, e.g. `en` and `de`.
+pub fn language_folders(path: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(path) else {
+        return Vec::new();
+    };
+    let mut folders: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    folders.sort();
+    folders
+}
+
+/// The folder names and the file name of a quote file in the language folder `root`, so all
+/// quotes in quotes/en/hellboy.toml have the tag `hellboy`.
+pub fn file_tags(root: &Path, file: &Path) -> BTreeSet<String> {
+    let mut tags = folder_tags(root, file);
+    tags.extend(file.file_stem().map(|s| normalize(&s.to_string_lossy())));
+    tags
+}
+
+/// A quote of the quote_languages with all its tags and weights.
+struct TaggedQuote {
+    quote: Quote,
+    /// The tags of the quote plus the folder names and the file name of its quote file, so
+    /// quotes/en/hellboy.toml matches images in a hellboy folder.
+    tags: BTreeSet<String>,
+    /// The weight of its quote file (by quote_weights and .nerdcli-weight files).
+    weight: f64,
+    /// The folder of its quote file, e.g. `en/scifi`, and the weight of the folder.
+    folder: String,
+    folder_weight: f64,
+}
+
+impl TaggedQuote {
+    /// A quote that is not stored in the quotes_dir, e.g. in the sidecar file of an image.
+    fn single(quote: Quote) -> TaggedQuote {
+        TaggedQuote {
+            tags: quote.tags.iter().map(|t| normalize(t)).collect(),
+            quote,
+            weight: 1.0,
+            folder: String::new(),
+            folder_weight: 1.0,
+        }
+    }
+}
+
+/// Reads all quotes in the folders of the `languages`, so every quote has the same chance, no
+/// matter how many quotes its file contains. A quote belongs to its `lang` (or else the language
+/// of its folder) and to those of the `languages` it has a translation for.
+fn all_quotes(path: &Path, languages: &[String], config: &NerdcliConfig) -> Vec<TaggedQuote> {
+    let mut all = Vec::new();
+    for folder in languages {
+        let Ok(files) = list_files(&path.join(folder), config) else {
+            continue;
+        };
+        for file in files.selectable() {
+            let file_tags = file_tags(&files.root, file);
+            let relative_folder = file
+                .parent()
+                .map(|p| relative_path(&files.root, p))
+                .unwrap_or_default();
+            let weight = files.weights.of(&relative_path(&files.root, file));
+            let folder_weight = files.weights.of(&relative_folder);
+
+            for quote in parse_quotes(file).quotes {
+                let mut tags = file_tags.clone();
+                tags.extend(quote.tags.iter().map(|t| normalize(t)));
+                let own_language = quote.lang.as_ref().unwrap_or(folder);
+                for language in languages {
+                    if language != own_language && !quote.has_translation(language) {
+                        continue;
                     }
+                    let mut quote = quote.clone();
+                    quote.language = Some(language.clone());
+                    all.push(TaggedQuote {
+                        quote,
+                        tags: tags.clone(),
+                        weight,
+                        folder: format!("{}/{}", folder, relative_folder),
+                        folder_weight,
+                    });
                 }
             }
         }
     }
-    Ok(())
+    all
 }
 
-pub fn list_files(path: &Path, config: &NerdcliConfig) -> io::Result<Vec<PathBuf>> {
-    let mut vec = Vec::new();
-    _list_files(&mut vec, &path, config)?;
-    Ok(vec)
+/// Quotes that are always shown with the image: the quotes in its sidecar file, the quotes
+/// referenced there by id and the quotes that name the image in their `images`.
+fn bound_quotes<'a>(
+    image: &ImageAsset,
+    sidecar_quotes: &'a [TaggedQuote],
+    all: &'a [TaggedQuote],
+) -> Vec<&'a TaggedQuote> {
+    let mut bound: Vec<&TaggedQuote> = sidecar_quotes.iter().collect();
+    for tagged in all {
+        let quote = &tagged.quote;
+        let by_id = quote
+            .id
+            .as_ref()
+            .is_some_and(|id| image.sidecar.quote_ids.contains(id));
+        let by_image = !quote.images.is_empty()
+            && image.relative.as_ref().is_some_and(|relative| {
+                PathFilter::new(&quote.images).is_ok_and(|f| f.is_match(Path::new(relative)))
+            });
+        if by_id || by_image {
+            bound.push(tagged);
+        }
+    }
+    bound
+}
+
+/// Selects one of the quotes that are allowed by the filter and not banned, weighted by their
+/// file weights and ratings. Recently shown quotes are only selected if `allow_repeats` is set
+/// and there is no other quote.
+fn choose_quote<R: Rng + ?Sized>(
+    quotes: &[&TaggedQuote],
+    config: &NerdcliConfig,
+    filter: &QuoteFilter,
+    ratings: &BTreeMap<String, Rating>,
+    history: &mut History,
+    allow_repeats: bool,
+    rng: &mut R,
+) -> Option<Quote> {
+    let shuffle = config.quote_selection.unwrap_or_default().is_shuffle();
+    let avoid_repeats = config.avoid_repeats.unwrap_or(0);
+    let weight = |q: &&TaggedQuote| {
+        // a full shuffle cycle only skips disabled files, like for images
+        let file_weight = match (shuffle, q.weight > 0.0) {
+            (true, true) => 1.0,
+            _ => q.weight,
+        };
+        file_weight * Ratings::weight(ratings, &q.quote.key())
+    };
+
+    let allowed: Vec<&TaggedQuote> = quotes
+        .iter()
+        .filter(|q| filter.allows(&q.quote))
+        .filter(|q| !Ratings::is_banned(ratings, &q.quote.key()))
+        .copied()
+        .collect();
+    let fresh: Vec<&TaggedQuote> = allowed
+        .iter()
+        .filter(|q| !history.quotes.skip(&q.quote.key(), avoid_repeats, shuffle))
+        .copied()
+        .collect();
+
+    let selected = match fresh.choose_weighted(rng, weight) {
+        Ok(quote) => quote.quote.clone(),
+        Err(_) if allow_repeats => allowed.choose_weighted(rng, weight).ok()?.quote.clone(),
+        Err(_) => return None,
+    };
+    history
+        .quotes
+        .record(&selected.key(), selected.text(), avoid_repeats, shuffle);
+    Some(selected)
 }
 
-pub fn get_quote(path: &Path, config: &NerdcliConfig) -> Quote {
+pub fn get_quote<R: Rng + ?Sized>(
+    path: &Path,
+    config: &NerdcliConfig,
+    filter: &QuoteFilter,
+    image: Option<&ImageAsset>,
+    history: &mut History,
+    rng: &mut R,
+) -> Quote {
     let default_quote = Quote {
-        text: "The only way to do great work is to love what you do.".to_string(),
+        text: "The only way to do great work is to love what you do.".into(),
         author: "Steve Jobs".to_string(),
-        source: None,
-        date: None,
+        ..Default::default()
     };
 
-    let mut rng = rand::rng();
-    let binding = "en".to_string();
-    let selected_language = config.quote_languages.choose(&mut rng).unwrap_or(&binding);
+    // banned quotes are never shown, rated ones more or less often
+    let ratings = Ratings::load().quotes;
+    let mode = config.quote_selection.unwrap_or_default();
 
-    let quote_files = list_files(&path.join(selected_language), config).unwrap_or_default();
+    let mut languages = config.quote_languages.weighted();
+    let names: Vec<String> = languages.iter().map(|(l, _)| l.clone()).collect();
+    let mut all: Vec<TaggedQuote> = all_quotes(path, &names, config);
+    // without configured languages, English quotes are shown if there are none in the locale
+    if all.is_empty() && config.quote_languages.is_empty() && names != ["en"] {
+        languages = vec![("en".to_string(), 1.0)];
+        all = all_quotes(path, &["en".to_string()], config);
+    }
+    all.retain(|q| filter.matches(&q.quote, &q.tags));
 
-    if quote_files.is_empty() {
-        println!(
-            "No quote files found for the selected language: {}",
-            selected_language
-        );
-        return default_quote;
+    if let Some(image) = image {
+        let sidecar_quotes: Vec<TaggedQuote> = image
+            .sidecar
+            .quotes
+            .iter()
+            .cloned()
+            .map(TaggedQuote::single)
+            .filter(|q| filter.matches(&q.quote, &q.tags))
+            .collect();
+        let bound = bound_quotes(image, &sidecar_quotes, &all);
+        if let Some(quote) = choose_quote(&bound, config, filter, &ratings, history, true, rng) {
+            return quote;
+        }
+
+        // prefer a quote that shares a tag with the image, e.g. a Hellboy quote for Hellboy art
+        if config.tag_pairing.unwrap_or(true) && !image.tags.is_empty() {
+            let matching: Vec<&TaggedQuote> = all
+                .iter()
+                .filter(|q| !q.tags.is_disjoint(&image.tags))
+                .collect();
+            if let Some(quote) =
+                choose_quote(&matching, config, filter, &ratings, history, false, rng)
+            {
+                return quote;
+            }
+        }
     }
 
-    let selected_file = quote_files.choose(&mut rng).unwrap();
+    // first the language is selected with its weight, then a quote in it
+    languages.retain(|(l, _)| all.iter().any(|q| q.quote.language.as_ref() == Some(l)));
+    let quotes: Vec<&TaggedQuote> = match languages.choose_weighted(rng, |(_, w)| *w) {
+        Ok((language, _)) => all
+            .iter()
+            .filter(|q| q.quote.language.as_ref() == Some(language))
+            .collect(),
+        Err(_) => all.iter().collect(),
+    };
+    if mode.is_shuffle() {
+        let keys: Vec<String> = quotes
+            .iter()
+            .filter(|q| filter.allows(&q.quote))
+            .filter(|q| !Ratings::is_banned(&ratings, &q.quote.key()))
+            .map(|q| q.quote.key())
+            .collect();
+        history.quotes.start_cycle(&keys);
+    }
 
-    let quotes: Vec<Quote> = parse_quotes(selected_file.to_str().unwrap()).quotes;
-    if quotes.is_empty() {
-        println!("No quotes found in the file: {}", selected_file.display());
-        return default_quote;
+    if mode == SelectionMode::ByFolder {
+        let mut folders: BTreeMap<&str, Vec<&TaggedQuote>> = BTreeMap::new();
+        for quote in &quotes {
+            folders.entry(&quote.folder).or_default().push(quote);
+        }
+        let folders: Vec<Vec<&TaggedQuote>> = folders.into_values().collect();
+        if let Ok(folder) = folders.choose_weighted(rng, |f| f[0].folder_weight) {
+            if let Some(quote) = choose_quote(folder, config, filter, &ratings, history, true, rng)
+            {
+                return quote;
+            }
+        }
     }
 
-    let sq = quotes.choose(&mut rng);
-    if sq.is_none() {
-        println!("No quotes found in the file: {}", selected_file.display());
-        return default_quote;
-    } else {
-        return sq.unwrap().clone();
+    choose_quote(&quotes, config, filter, &ratings, history, true, rng).unwrap_or_else(|| {
+        if filter.is_active() {
+            println!("No quotes match the quote filters");
+        } else {
+            println!(
+                "No quotes found for the selected languages: {}",
+                config.quote_languages
+            );
+        }
+        default_quote
+    })
+}
+
+/// Reads the quotes of a file. Fortune databases are not always UTF-8, so invalid characters are
+/// replaced.
+pub fn read_quotes(path: &Path) -> Result<Vec<Quote>, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let format = QuoteFormat::of(path).unwrap_or(QuoteFormat::Toml);
+    let mut quotes = format.parse(&String::from_utf8_lossy(&bytes))?;
+    for quote in quotes.iter_mut() {
+        quote.file = Some(path.to_path_buf());
     }
+    Ok(quotes)
 }
 
-pub fn parse_quotes(filename: &str) -> Quotes {
-    let contents = match fs::read_to_string(filename) {
-        // If successful return the files text as `contents`.
-        // `c` is a local variable.
-        Ok(c) => c,
-        // Handle the `error` case.
-        Err(_) => {
-            // Write `msg` to `stderr`.
-            eprintln!("Could not read quote file `{}`", filename);
-            // Exit the program with exit code `1`.
-            std::process::exit(1);
-        }
-    };
-    let data: Quotes = match toml::from_str(&contents) {
-        // If successful, return data as `Data` struct.
-        // `d` is a local variable.
-        Ok(d) => d,
-        // Handle the `error` case.
+/// Reads the quotes of a file in the quotes_dir. A broken file is skipped with a warning, so it
+/// doesn't stop the greeting.
+pub fn parse_quotes(path: &Path) -> Quotes {
+    match read_quotes(path) {
+        Ok(quotes) => Quotes { quotes },
         Err(e) => {
-            // Write `msg` to `stderr`.
-            eprintln!("Unable to load quote data from `{}`: {}", filename, e);
-            // Exit the program with exit code `1`.
-            std::process::exit(1);
+            eprintln!("Skipping the quote file `{}`: {}", path.display(), e);
+            Quotes::default()
         }
-    };
-
-    data
+    }
 }
@@ -0,0 +1,179 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{self, PathBuf},
+    process::exit,
+};
+
+use clap::Args;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::history::{History, Shown};
+use crate::settings::state_dir;
+
+#[derive(Args)]
+pub struct Target {
+    #[arg(help = "The image to act on, e.g. to undo a ban (default: the last shown image)")]
+    image: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        conflicts_with = "image",
+        help = "Act on the last shown quote instead of the last shown image"
+    )]
+    quote: bool,
+}
+
+#[derive(Args)]
+pub struct MarkArgs {
+    #[command(flatten)]
+    pub target: Target,
+
+    #[arg(short, long, help = "Remove the mark again")]
+    pub undo: bool,
+}
+
+#[derive(Args)]
+pub struct RateArgs {
+    #[arg(
+        value_parser = clap::value_parser!(u8).range(1..=5),
+        help = "1 (shown rarely) to 5 (shown often)"
+    )]
+    pub stars: u8,
+
+    #[command(flatten)]
+    pub target: Target,
+}
+
+/// What `nerdcli fav`, `ban` and `rate` change.
+pub enum Change {
+    Favorite(bool),
+    Banned(bool),
+    Stars(u8),
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Rating {
+    /// The path of the image or the text of the quote, to make the file readable.
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub banned: bool,
+    pub stars: Option<u8>,
+}
+
+impl Rating {
+    /// The factor for the selection weight: favorites are shown three times as often,
+    /// 3 stars count like no rating, banned entries are never shown.
+    pub fn weight(&self) -> f64 {
+        if self.banned {
+            return 0.0;
+        }
+        let favorite = if self.favorite { 3.0 } else { 1.0 };
+        favorite * self.stars.map_or(1.0, |s| s as f64 / 3.0)
+    }
+}
+
+/// Favorites, bans and ratings of images (by their path) and quotes (by their key),
+/// kept in the state directory.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Ratings {
+    #[serde(default)]
+    pub images: BTreeMap<String, Rating>,
+    #[serde(default)]
+    pub quotes: BTreeMap<String, Rating>,
+}
+
+fn ratings_file() -> PathBuf {
+    state_dir().join("ratings.json")
+}
+
+impl Ratings {
+    /// Loads the ratings. A missing or broken file results in no ratings.
+    pub fn load() -> Ratings {
+        fs::read_to_string(ratings_file())
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = ratings_file();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(&path, json)
+    }
+
+    pub fn is_banned(ratings: &BTreeMap<String, Rating>, key: &str) -> bool {
+        ratings.get(key).is_some_and(|r| r.banned)
+    }
+
+    pub fn weight(ratings: &BTreeMap<String, Rating>, key: &str) -> f64 {
+        ratings.get(key).map_or(1.0, Rating::weight)
+    }
+}
+
+pub fn run(target: &Target, change: Change) {
+    let history = History::load();
+    let (track, what) = if target.quote {
+        (&history.quotes, "quote")
+    } else {
+        (&history.images, "image")
+    };
+    let given = target.image.as_ref().map(|image| {
+        let path = path::absolute(image)
+            .unwrap_or(image.clone())
+            .to_string_lossy()
+            .into_owned();
+        Shown {
+            key: path.clone(),
+            label: path,
+        }
+    });
+    let Some(shown) = given.or(track.last.clone()) else {
+        eprintln!("No {} has been shown yet.", what);
+        exit(1);
+    };
+
+    let mut ratings = Ratings::load();
+    let entries = if target.quote {
+        &mut ratings.quotes
+    } else {
+        &mut ratings.images
+    };
+    let rating = entries.entry(shown.key.clone()).or_default();
+    rating.label = shown.label.clone();
+
+    let message = match change {
+        Change::Favorite(true) => "is a favorite now".to_string(),
+        Change::Favorite(false) => "is no favorite anymore".to_string(),
+        Change::Banned(true) => "will not be shown again".to_string(),
+        Change::Banned(false) => "is not banned anymore".to_string(),
+        Change::Stars(stars) => format!("is rated with {} of 5", stars),
+    };
+    match change {
+        Change::Favorite(favorite) => rating.favorite = favorite,
+        Change::Banned(banned) => rating.banned = banned,
+        Change::Stars(stars) => rating.stars = Some(stars),
+    }
+
+    // entries without any mark are removed, so the file does not grow with every `--undo`
+    if !rating.favorite && !rating.banned && rating.stars.is_none() {
+        entries.remove(&shown.key);
+    }
+
+    if let Err(e) = ratings.save() {
+        eprintln!(
+            "Could not write the ratings `{}`: {}",
+            ratings_file().display(),
+            e
+        );
+        exit(1);
+    }
+    println!("The {} `{}` {}.", what, shown.label, message);
+}
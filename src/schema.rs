@@ -0,0 +1,239 @@
+use std::{fs, path::Path};
+
+use serde_json::{json, Map, Value};
+
+use crate::migrations::CONFIG_VERSION;
+
+/// File name of the JSON schema in the config directory. The nerdcli.toml refers to it with a
+/// `#:schema` directive, which is understood by taplo and Even Better TOML.
+pub const SCHEMA_FILE: &str = "nerdcli.schema.json";
+
+fn color(description: &str) -> Value {
+    let channel = json!({ "type": "integer", "minimum": 0, "maximum": 255 });
+    json!({
+        "description": description,
+        "type": "object",
+        "properties": { "r": channel, "g": channel, "b": channel },
+        "required": ["r", "g", "b"],
+        "additionalProperties": false
+    })
+}
+
+fn selection(description: &str) -> Value {
+    json!({
+        "description": description,
+        "enum": ["weighted", "by_folder", "shuffle"]
+    })
+}
+
+fn weights(description: &str) -> Value {
+    json!({
+        "description": description,
+        "type": "object",
+        "additionalProperties": { "type": "number", "minimum": 0 }
+    })
+}
+
+fn string_list(description: &str) -> Value {
+    json!({
+        "description": description,
+        "type": "array",
+        "items": { "type": "string" }
+    })
+}
+
+/// The settings that can be used on the top level as well as in profiles.
+fn settings() -> Map<String, Value> {
+    let percentage = |description: &str| {
+        json!({
+            "description": description,
+            "type": "integer",
+            "minimum": 0,
+            "maximum": 100
+        })
+    };
+
+    let settings = json!({
+        "max_width_percentage": percentage("Maximum width of the image relative to the terminal width."),
+        "max_height_percentage": percentage("Maximum height of the image relative to the terminal height."),
+        "layout": {
+            "description": "Arrangement of image and quote.",
+            "enum": ["ROW", "ROW_CENTERED", "COL", "COL_CENTERED"]
+        },
+        "show_quotes": {
+            "description": "If false, only the image is shown.",
+            "type": "boolean"
+        },
+        "margin_left": {
+            "description": "The gap left of the image (in terminal cells).",
+            "type": "integer",
+            "minimum": 0
+        },
+        "margin_top": {
+            "description": "The gap above the image (in terminal cells).",
+            "type": "integer"
+        },
+        "image_dir": {
+            "description": "Directory to search images in, relative to the config directory.",
+            "type": "string"
+        },
+        "quotes_dir": {
+            "description": "Directory to search quotes in, relative to the config directory.",
+            "type": "string"
+        },
+        "quote_languages": {
+            "description": "Folder names in the quotes_dir to select quotes from, as a list or with weights, e.g. { en = 2, de = 1 }. Defaults to the language of the locale (LANG).",
+            "anyOf": [
+                { "type": "array", "items": { "type": "string" } },
+                { "type": "object", "additionalProperties": { "type": "number", "minimum": 0 } }
+            ]
+        },
+        "quote_color": color("Color of the quote text."),
+        "source_color": color("Color of source and date."),
+        "author_color": color("Color of the author."),
+        "image_types": string_list("Image types to show, e.g. [\"png\", \"jpg\"] (case insensitive, jpeg is the same as jpg). All images are considered if empty."),
+        "image_index": {
+            "description": "Cache the list of images, so the image_dir is not read completely on every start. Defaults to true.",
+            "type": "boolean"
+        },
+        "thumbnail_cache_mb": {
+            "description": "Size limit of the cache for downscaled images in MB. 0 disables the cache. Defaults to 100.",
+            "type": "integer",
+            "minimum": 0
+        },
+        "crop_to_fit": {
+            "description": "Crop images to the space next to (or above) the quote, around the focus of their sidecar file or their most detailed part. At most half of an image is cropped. Defaults to false.",
+            "type": "boolean"
+        },
+        "image_effects": {
+            "description": "Filters applied to the images in this order. dither and ordered_dither reduce the colors to the 256 color palette, only for images drawn with block characters. tint and border use the effect_color. A sidecar file of an image can replace them with effects = [...].",
+            "type": "array",
+            "items": { "enum": ["grayscale", "sepia", "tint", "dither", "ordered_dither", "rounded_corners", "border"] }
+        },
+        "effect_color": color("Color of the tint and border image_effects. Defaults to the quote_color."),
+        "sniff_images": {
+            "description": "Detect images without a known file extension by their content.",
+            "type": "boolean"
+        },
+        "image_filter": string_list("Glob patterns relative to the image_dir, e.g. \"comics/**\". Patterns starting with ! exclude images, e.g. \"!**/nsfw/**\". All images are considered if there is no including pattern."),
+        "quote_filter": string_list("Glob patterns relative to the language folder in the quotes_dir, e.g. \"scifi/**\". Patterns starting with ! exclude quote files. All quote files are considered if there is no including pattern."),
+        "image_selection": selection("weighted: every image is selected with the weight of its folder. by_folder: first a folder is selected, then an image in it. shuffle: every image is shown once before any image is shown again. Defaults to weighted."),
+        "quote_selection": selection("weighted: every quote of the quote_languages is selected with the weight of its file or folder. by_folder: first a folder is selected, then a quote in it. shuffle: every quote is shown once before any quote is shown again. Defaults to weighted."),
+        "avoid_repeats": {
+            "description": "Number of recently shown images and quotes that are not selected again. Defaults to 0.",
+            "type": "integer",
+            "minimum": 0
+        },
+        "selection": {
+            "description": "How often image and quote change: random (every start), daily, hourly or per_session. Defaults to random.",
+            "enum": ["random", "daily", "hourly", "per_session"]
+        },
+        "tag_pairing": {
+            "description": "Prefer quotes that share a tag with the image (folder names, sidecar files, tags.toml). Defaults to true.",
+            "type": "boolean"
+        },
+        "nsfw_quotes": {
+            "description": "Also show quotes marked with nsfw = true. Defaults to false.",
+            "type": "boolean"
+        },
+        "quote_author": {
+            "description": "Only show quotes whose author or character contains this text (case insensitive).",
+            "type": "string"
+        },
+        "quote_tags": string_list("Only show quotes with one of these tags (including the folder and file names of the quote file)."),
+        "quote_source": {
+            "description": "Only show quotes whose source or work contains this text (case insensitive).",
+            "type": "string"
+        },
+        "quote_max_lines": {
+            "description": "Only show quotes with at most this many lines. Quotes that do not fit into the terminal are never shown.",
+            "type": "integer",
+            "minimum": 1
+        },
+        "quote_grep": {
+            "description": "Only show quotes whose text matches this regular expression, e.g. \"(?i)robot\".",
+            "type": "string"
+        },
+        "image_weights": weights("Weights of folders or images by their path relative to the image_dir, e.g. { \"hellboy\" = 3 }. Overrides .nerdcli-weight files. Defaults to 1, 0 disables a folder."),
+        "quote_weights": weights("Weights of folders or quote files by their path relative to the language folder, e.g. { \"scifi.toml\" = 2 }. Overrides .nerdcli-weight files. Defaults to 1, 0 disables a folder."),
+    });
+
+    match settings {
+        Value::Object(m) => m,
+        _ => unreachable!(),
+    }
+}
+
+pub fn config_schema() -> Value {
+    let mut profile = settings();
+    profile.insert(
+        "when".to_string(),
+        json!({
+            "description": "Rules to select the profile automatically. All rules must match.",
+            "type": "object",
+            "properties": {
+                "hostname": { "$ref": "#/$defs/patterns" },
+                "term_program": { "$ref": "#/$defs/patterns" },
+                "cwd": { "$ref": "#/$defs/patterns" }
+            },
+            "additionalProperties": false
+        }),
+    );
+
+    let mut properties = settings();
+    properties.insert(
+        "config_version".to_string(),
+        json!({
+            "description": "The version of the config format. Older config files are migrated automatically, do not change it.",
+            "type": "integer",
+            "maximum": CONFIG_VERSION
+        }),
+    );
+    properties.insert(
+        "profiles".to_string(),
+        json!({
+            "description": "Named profiles that override any setting, selected with --profile, NERDCLI_PROFILE or their `when` rules.",
+            "type": "object",
+            "additionalProperties": {
+                "type": "object",
+                "properties": profile,
+                "additionalProperties": false
+            }
+        }),
+    );
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "nerdcli.toml",
+        "description": "Configuration of nerdcli",
+        "type": "object",
+        "properties": properties,
+        "required": ["show_quotes", "image_dir", "quotes_dir", "quote_color", "source_color", "author_color", "image_types"],
+        "additionalProperties": false,
+        "$defs": {
+            "patterns": {
+                "description": "A glob pattern or a list of glob patterns, of which one must match.",
+                "anyOf": [
+                    { "type": "string" },
+                    { "type": "array", "items": { "type": "string" } }
+                ]
+            }
+        }
+    })
+}
+
+pub fn schema_string() -> String {
+    serde_json::to_string_pretty(&config_schema()).unwrap()
+}
+
+/// Writes the JSON schema to the config directory, so editors can validate the nerdcli.toml.
+pub fn write_schema_file(config_dir: &Path) {
+    let path = config_dir.join(SCHEMA_FILE);
+    if let Err(e) = fs::write(&path, schema_string() + "\n") {
+        eprintln!(
+            "Could not write the JSON schema to `{}`: {}",
+            path.display(),
+            e
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use rand::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::index::fnv_hash64;
+
+/// Name of the file that sets the weight of a folder (and its sub folders), e.g. `3` or `0.5`.
+pub const WEIGHT_FILE: &str = ".nerdcli-weight";
+
+/// How an image or a quote is selected from all candidates.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionMode {
+    /// Every file is selected with the weight of its folder (1 if no weight is set).
+    #[default]
+    Weighted,
+    /// First a folder is selected (with its weight), then a file in the folder. A folder with a
+    /// few favorites is selected as often as a folder with hundreds of screenshots.
+    ByFolder,
+    /// Every file is shown once (in random order) before any file is shown again.
+    /// Files with a weight of 0 are skipped, other weights are ignored.
+    Shuffle,
+}
+
+impl SelectionMode {
+    pub fn is_shuffle(self) -> bool {
+        self == SelectionMode::Shuffle
+    }
+}
+
+/// How often the selected image and quote change.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Rotation {
+    /// A new image and quote on every start.
+    #[default]
+    Random,
+    /// Every terminal opened on the same day shows the same image and quote.
+    Daily,
+    Hourly,
+    /// The same image and quote within a login session (or terminal session, if it has an id).
+    /// Without a session id, only unix systems use the parent shell, others select randomly.
+    PerSession,
+}
+
+impl Rotation {
+    /// The seed for the random selection, or None if every start selects anew.
+    pub fn seed(self) -> Option<u64> {
+        let now = Local::now();
+        let key = match self {
+            Rotation::Random => return None,
+            Rotation::Daily => format!("daily|{}", now.format("%Y-%m-%d")),
+            Rotation::Hourly => format!("hourly|{}", now.format("%Y-%m-%d %H")),
+            Rotation::PerSession => format!("session|{}", session_id()?),
+        };
+        Some(fnv_hash64(key.as_bytes()))
+    }
+}
+
+fn session_id() -> Option<String> {
+    ["XDG_SESSION_ID", "TERM_SESSION_ID", "TMUX"]
+        .iter()
+        .find_map(|var| env::var(var).ok().filter(|v| !v.is_empty()))
+        .or_else(parent_shell)
+}
+
+/// Without a session id, the shell that started nerdcli is the session.
+#[cfg(unix)]
+fn parent_shell() -> Option<String> {
+    Some(std::os::unix::process::parent_id().to_string())
+}
+
+#[cfg(not(unix))]
+fn parent_shell() -> Option<String> {
+    None
+}
+
+/// Weights of folders or files by their path relative to the content directory.
+/// The weight of a file is the weight of the file itself or its nearest folder that has a weight.
+#[derive(Default)]
+pub struct Weights {
+    weights: BTreeMap<String, f64>,
+    /// Factors of single files from their ratings, see `nerdcli rate`.
+    ratings: BTreeMap<String, f64>,
+}
+
+impl Weights {
+    /// Weights from the config file take precedence over the weight files in the folders.
+    pub fn new(configured: &BTreeMap<String, f64>, from_files: BTreeMap<String, f64>) -> Weights {
+        let mut weights = from_files;
+        for (path, weight) in configured {
+            weights.insert(path.trim_matches('/').to_string(), *weight);
+        }
+        Weights {
+            weights,
+            ratings: BTreeMap::new(),
+        }
+    }
+
+    pub fn set_rating(&mut self, relative: String, factor: f64) {
+        self.ratings.insert(relative, factor);
+    }
+
+    /// Reads the weight files of all folders (below `root`) that contain one of the files.
+    pub fn read_weight_files(root: &Path, files: &[PathBuf]) -> BTreeMap<String, f64> {
+        let mut dirs = BTreeSet::new();
+        for file in files {
+            let mut dir = file.parent();
+            while let Some(d) = dir {
+                if !d.starts_with(root) || !dirs.insert(d.to_path_buf()) {
+                    break;
+                }
+                dir = d.parent();
+            }
+        }
+
+        dirs.into_iter()
+            .filter_map(|dir| {
+                let weight = read_weight_file(&dir)?;
+                Some((relative_path(root, &dir), weight))
+            })
+            .collect()
+    }
+
+    pub fn of(&self, relative: &str) -> f64 {
+        self.ratings.get(relative).unwrap_or(&1.0) * self.configured_of(relative)
+    }
+
+    fn configured_of(&self, relative: &str) -> f64 {
+        let mut path = relative;
+        loop {
+            if let Some(weight) = self.weights.get(path) {
+                return weight.max(0.0);
+            }
+            match path.rfind('/') {
+                Some(i) => path = &path[..i],
+                None if !path.is_empty() => path = "",
+                None => return 1.0,
+            }
+        }
+    }
+}
+
+pub fn read_weight_file(dir: &Path) -> Option<f64> {
+    let contents = fs::read_to_string(dir.join(WEIGHT_FILE)).ok()?;
+    match contents.trim().parse::<f64>() {
+        Ok(w) => Some(w),
+        Err(_) => {
+            eprintln!(
+                "Ignoring `{}`: `{}` is not a number",
+                dir.join(WEIGHT_FILE).display(),
+                contents.trim()
+            );
+            None
+        }
+    }
+}
+
+/// The path relative to `root`, separated by `/`.
+pub fn relative_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Files to select from, with the weights of their folders.
+pub struct Candidates {
+    pub root: PathBuf,
+    pub files: Vec<PathBuf>,
+    pub weights: Weights,
+}
+
+impl Candidates {
+    /// Selects a file. Files for which `skip` is true (e.g. recently shown ones) are only
+    /// selected if there is no other file left.
+    pub fn choose<R: Rng + ?Sized>(
+        &self,
+        mode: SelectionMode,
+        skip: impl Fn(&Path) -> bool,
+        rng: &mut R,
+    ) -> Option<&PathBuf> {
+        let remaining: Vec<&PathBuf> = self.files.iter().filter(|f| !skip(f)).collect();
+        self.choose_from(&remaining, mode, rng)
+            .or_else(|| self.choose_from(&self.files.iter().collect::<Vec<_>>(), mode, rng))
+    }
+
+    /// All files that can be selected at all (with a weight above 0).
+    pub fn selectable(&self) -> impl Iterator<Item = &PathBuf> {
+        self.files
+            .iter()
+            .filter(|f| self.weights.of(&relative_path(&self.root, f)) > 0.0)
+    }
+
+    fn choose_from<'a, R: Rng + ?Sized>(
+        &self,
+        files: &[&'a PathBuf],
+        mode: SelectionMode,
+        rng: &mut R,
+    ) -> Option<&'a PathBuf> {
+        let weight = |file: &PathBuf| self.weights.of(&relative_path(&self.root, file));
+
+        match mode {
+            SelectionMode::Weighted => files.choose_weighted(rng, |f| weight(f)).ok().copied(),
+            SelectionMode::Shuffle => files
+                .choose_weighted(rng, |f| if weight(f) > 0.0 { 1.0 } else { 0.0 })
+                .ok()
+                .copied(),
+            SelectionMode::ByFolder => {
+                let mut folders: BTreeMap<String, Vec<&PathBuf>> = BTreeMap::new();
+                for file in files {
+                    let folder = file
+                        .parent()
+                        .map(|p| relative_path(&self.root, p))
+                        .unwrap_or_default();
+                    folders.entry(folder).or_default().push(*file);
+                }
+                let folders: Vec<(String, Vec<&PathBuf>)> = folders.into_iter().collect();
+                let (_, files) = folders
+                    .choose_weighted(rng, |(folder, _)| self.weights.of(folder))
+                    .ok()?;
+                files.choose_weighted(rng, |f| weight(f)).ok().copied()
+            }
+        }
+    }
+}
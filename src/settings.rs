@@ -1,14 +1,24 @@
 use crate::default_settings::ask_for_config_creation;
+use crate::effects::ImageEffect;
+use crate::languages::QuoteLanguages;
+use crate::migrations::{migrate_config_file, CONFIG_VERSION};
+use crate::profiles::{take_all_profiles, take_profile};
 use crate::quotes::Color;
+use crate::selection::{Rotation, SelectionMode};
 use crate::Cli;
 use directories::ProjectDirs;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
+use std::path::PathBuf;
 use std::process::exit;
-use toml;
+use toml::{Table, Value};
 
-#[derive(Deserialize, Default, Clone)]
+#[derive(Deserialize, Serialize, Default, Clone)]
 pub struct NerdcliConfig {
+    #[serde(default)]
+    pub config_version: i64,
     pub max_width_percentage: Option<u16>,
     pub max_height_percentage: Option<u16>,
     pub layout: Option<String>,
@@ -17,131 +27,641 @@ pub struct NerdcliConfig {
     pub margin_top: Option<i16>,
     pub image_dir: String,
     pub quotes_dir: String,
-    pub quote_languages: Vec<String>,
+    #[serde(default)]
+    pub quote_languages: QuoteLanguages,
     pub quote_color: Color,
     pub source_color: Color,
     pub author_color: Color,
     pub image_types: Vec<String>,
-    pub include_folders: Vec<String>,
+    #[serde(default)]
+    pub sniff_images: bool,
+    pub image_index: Option<bool>,
+    pub thumbnail_cache_mb: Option<u64>,
+    pub crop_to_fit: Option<bool>,
+    #[serde(default)]
+    pub image_effects: Vec<ImageEffect>,
+    pub effect_color: Option<Color>,
+    #[serde(default)]
+    pub image_filter: Vec<String>,
+    #[serde(default)]
+    pub quote_filter: Vec<String>,
+    pub image_selection: Option<SelectionMode>,
+    pub quote_selection: Option<SelectionMode>,
+    pub avoid_repeats: Option<usize>,
+    pub selection: Option<Rotation>,
+    pub tag_pairing: Option<bool>,
+    #[serde(default)]
+    pub nsfw_quotes: bool,
+    pub quote_author: Option<String>,
+    #[serde(default)]
+    pub quote_tags: Vec<String>,
+    pub quote_source: Option<String>,
+    pub quote_max_lines: Option<usize>,
+    pub quote_grep: Option<String>,
+    #[serde(default)]
+    pub image_weights: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub quote_weights: BTreeMap<String, f64>,
+    #[serde(skip_serializing)]
     pub config_base_path: Option<String>,
+    #[serde(skip)]
+    pub profile: Option<String>,
+    #[serde(skip)]
+    pub origins: BTreeMap<String, ValueOrigin>,
 }
 
-fn print_option<T, A>(name: &str, x: Option<T>, a: Option<A>)
+/// Where the effective value of a setting came from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValueOrigin {
+    Default,
+    File,
+    Profile(String),
+    Env(String),
+    Cli(String),
+}
+
+impl fmt::Display for ValueOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueOrigin::Default => write!(f, "default"),
+            ValueOrigin::File => write!(f, "config file"),
+            ValueOrigin::Profile(name) => write!(f, "profile {}", name),
+            ValueOrigin::Env(var) => write!(f, "env {}", var),
+            ValueOrigin::Cli(arg) => write!(f, "cli {}", arg),
+        }
+    }
+}
+
+/// The value types a setting can have. Used to parse environment variables.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Text,
+    Number,
+    Flag,
+    List,
+    Color,
+    Table,
+}
+
+/// All settings of the config file that can be overwritten via environment variables.
+/// The variable name is the upper case key prefixed with `NERDCLI_`, e.g. `NERDCLI_LAYOUT`.
+const SETTINGS: &[(&str, Kind)] = &[
+    ("max_width_percentage", Kind::Number),
+    ("max_height_percentage", Kind::Number),
+    ("layout", Kind::Text),
+    ("show_quotes", Kind::Flag),
+    ("margin_left", Kind::Number),
+    ("margin_top", Kind::Number),
+    ("image_dir", Kind::Text),
+    ("quotes_dir", Kind::Text),
+    ("quote_languages", Kind::List),
+    ("quote_color", Kind::Color),
+    ("source_color", Kind::Color),
+    ("author_color", Kind::Color),
+    ("image_types", Kind::List),
+    ("sniff_images", Kind::Flag),
+    ("image_index", Kind::Flag),
+    ("thumbnail_cache_mb", Kind::Number),
+    ("crop_to_fit", Kind::Flag),
+    ("image_effects", Kind::List),
+    ("effect_color", Kind::Color),
+    ("image_filter", Kind::List),
+    ("quote_filter", Kind::List),
+    ("image_selection", Kind::Text),
+    ("quote_selection", Kind::Text),
+    ("avoid_repeats", Kind::Number),
+    ("selection", Kind::Text),
+    ("tag_pairing", Kind::Flag),
+    ("nsfw_quotes", Kind::Flag),
+    ("quote_author", Kind::Text),
+    ("quote_tags", Kind::List),
+    ("quote_source", Kind::Text),
+    ("quote_max_lines", Kind::Number),
+    ("quote_grep", Kind::Text),
+    ("image_weights", Kind::Table),
+    ("quote_weights", Kind::Table),
+];
+
+fn env_var_name(key: &str) -> String {
+    format!("NERDCLI_{}", key.to_uppercase())
+}
+
+/// Parses the raw value of an environment variable into a toml value of the given kind.
+/// Lists may be given comma separated (`png,gif`), colors as `r,g,b` or `#rrggbb`.
+/// Everything else is accepted in toml syntax as well (`["png", "gif"]`, `{r=1, g=2, b=3}`).
+fn parse_env_value(kind: Kind, raw: &str) -> Result<Value, String> {
+    let raw = raw.trim();
+    if kind == Kind::Text {
+        return Ok(Value::String(raw.to_string()));
+    }
+
+    if let Ok(mut table) = toml::from_str::<Table>(&format!("value = {}", raw)) {
+        if let Some(value) = table.remove("value") {
+            return Ok(value);
+        }
+    }
+
+    match kind {
+        Kind::List => Ok(Value::Array(
+            raw.split(',')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| Value::String(s.to_string()))
+                .collect(),
+        )),
+        Kind::Color => parse_color(raw)
+            .map(|(r, g, b)| {
+                let mut color = Table::new();
+                color.insert("r".to_string(), Value::Integer(r as i64));
+                color.insert("g".to_string(), Value::Integer(g as i64));
+                color.insert("b".to_string(), Value::Integer(b as i64));
+                Value::Table(color)
+            })
+            .ok_or(format!("`{}` is not a color (use r,g,b or #rrggbb)", raw)),
+        Kind::Flag => match raw.to_lowercase().as_str() {
+            "1" | "yes" | "on" => Ok(Value::Boolean(true)),
+            "0" | "no" | "off" => Ok(Value::Boolean(false)),
+            _ => Err(format!("`{}` is not a boolean", raw)),
+        },
+        Kind::Table => Err(format!("`{}` is not a table (use {{key = value}})", raw)),
+        _ => Err(format!("`{}` is not a number", raw)),
+    }
+}
+
+/// Parses a value given on the command line or in an environment variable for the given key.
+/// Keys that are not known settings (e.g. in profiles) are read as toml, or as plain text.
+pub fn parse_setting(key: &str, raw: &str) -> Result<Value, String> {
+    match SETTINGS.iter().find(|(k, _)| *k == key) {
+        Some((_, kind)) => parse_env_value(*kind, raw),
+        None => match toml::from_str::<Table>(&format!("value = {}", raw.trim())) {
+            Ok(mut table) => Ok(table
+                .remove("value")
+                .unwrap_or(Value::String(raw.to_string()))),
+            Err(_) => Ok(Value::String(raw.to_string())),
+        },
+    }
+}
+
+fn parse_color(raw: &str) -> Option<(u8, u8, u8)> {
+    if let Some(hex) = raw.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some((channel(0)?, channel(2)?, channel(4)?));
+    }
+
+    let channels: Vec<u8> = raw
+        .split(',')
+        .map(|c| c.trim().parse::<u8>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    match channels[..] {
+        [r, g, b] => Some((r, g, b)),
+        _ => None,
+    }
+}
+
+/// Merges the active profile, the environment variables and the command line arguments into the
+/// table read from the config file. Returns the origin of every setting.
+fn apply_overrides(
+    table: &mut Table,
+    profile: Option<(String, Table)>,
+    cli: &Cli,
+) -> BTreeMap<String, ValueOrigin> {
+    let mut origins = BTreeMap::new();
+
+    for (key, _) in SETTINGS {
+        let origin = if table.contains_key(*key) {
+            ValueOrigin::File
+        } else {
+            ValueOrigin::Default
+        };
+        origins.insert(key.to_string(), origin);
+    }
+
+    if let Some((name, settings)) = profile {
+        for (key, value) in settings {
+            origins.insert(key.clone(), ValueOrigin::Profile(name.clone()));
+            table.insert(key, value);
+        }
+    }
+
+    for (key, kind) in SETTINGS {
+        let var = env_var_name(key);
+        if let Ok(raw) = std::env::var(&var) {
+            match parse_env_value(*kind, &raw) {
+                Ok(value) => {
+                    table.insert(key.to_string(), value);
+                    origins.insert(key.to_string(), ValueOrigin::Env(var));
+                }
+                Err(e) => {
+                    eprintln!("Invalid value in environment variable {}: {}", var, e);
+                    exit(1);
+                }
+            }
+        }
+    }
+
+    let cli_values: [(&str, &str, Option<Value>); 10] = [
+        (
+            "max_width_percentage",
+            "--max-width-percentage",
+            cli.max_width_percentage.map(|v| Value::Integer(v.into())),
+        ),
+        (
+            "max_height_percentage",
+            "--max-height-percentage",
+            cli.max_height_percentage.map(|v| Value::Integer(v.into())),
+        ),
+        (
+            "margin_left",
+            "--left",
+            cli.left.map(|v| Value::Integer(v.into())),
+        ),
+        (
+            "margin_top",
+            "--above",
+            cli.above.map(|v| Value::Integer(v.into())),
+        ),
+        ("layout", "--layout", cli.layout.clone().map(Value::String)),
+        (
+            "quote_author",
+            "--author",
+            cli.author.clone().map(Value::String),
+        ),
+        (
+            "quote_tags",
+            "--tag",
+            (!cli.tag.is_empty())
+                .then(|| Value::Array(cli.tag.iter().cloned().map(Value::String).collect())),
+        ),
+        (
+            "quote_source",
+            "--source",
+            cli.source.clone().map(Value::String),
+        ),
+        (
+            "quote_max_lines",
+            "--max-lines",
+            cli.max_lines.map(|v| Value::Integer(v as i64)),
+        ),
+        ("quote_grep", "--grep", cli.grep.clone().map(Value::String)),
+    ];
+    for (key, arg, value) in cli_values {
+        if let Some(value) = value {
+            table.insert(key.to_string(), value);
+            origins.insert(key.to_string(), ValueOrigin::Cli(arg.to_string()));
+        }
+    }
+
+    origins
+}
+
+fn print_option<T>(name: &str, x: Option<T>, origin: Option<&ValueOrigin>)
 where
     T: std::fmt::Debug,
-    A: std::fmt::Debug,
 {
+    let origin = match origin {
+        Some(o) => format!("\t({})", o),
+        None => "".to_string(),
+    };
     if let Some(x) = x {
-        println!(
-            "\t{name}: {x:?} {ov}",
-            name = name,
-            x = x,
-            ov = if let Some(a) = a {
-                format!("\t(overwritten: {a:?})", a = a)
-            } else {
-                "".to_string()
-            }
-        );
+        println!("\t{name}: {x:?} {origin}");
     } else {
-        println!(
-            "\t{name}: None {ov}",
-            ov = if let Some(a) = a {
-                format!("\t(overwritten: {a:?})", a = a)
-            } else {
-                "".to_string()
-            }
-        );
+        println!("\t{name}: None {origin}");
     }
 }
 
-pub fn print_config(data: &NerdcliConfig, cli: &Cli) {
-    let none: Option<String> = None;
+pub fn print_config(data: &NerdcliConfig) {
+    let origin = |key: &str| data.origins.get(key);
 
     println!("\nNerd-CLI configuration:");
-    print_option::<String, String>(
-        "\tConfig and content path: {}",
+    print_option(
+        "Config and content path",
         data.config_base_path.clone(),
-        none,
+        None,
     );
+    print_option("Active profile", data.profile.clone(), None);
 
     println!("\n\tImage settings:");
     print_option(
         "max_width_percentage",
         data.max_width_percentage,
-        cli.max_width_percentage.clone(),
+        origin("max_width_percentage"),
     );
     print_option(
         "max_height_percentage",
         data.max_height_percentage,
-        cli.max_height_percentage.clone(),
+        origin("max_height_percentage"),
     );
-    print_option("margin top", data.margin_top, cli.above.clone());
-    print_option("margin left", data.margin_left, cli.left.clone());
+    print_option("margin_top", data.margin_top, origin("margin_top"));
+    print_option("margin_left", data.margin_left, origin("margin_left"));
 
     println!("\n\tQuote settings:");
-    println!("\tshow_quotes: {:?}", data.show_quotes);
+    print_option("show_quotes", Some(data.show_quotes), origin("show_quotes"));
+    print_option(
+        "quote_languages",
+        Some(data.quote_languages.to_string()),
+        origin("quote_languages"),
+    );
 
     println!("\n\tLayout settings:");
-    print_option("layout", data.layout.clone(), cli.layout.clone());
+    print_option("layout", data.layout.clone(), origin("layout"));
+
+    println!("\n\tColor settings:");
+    for (name, color) in [
+        ("quote_color", &data.quote_color),
+        ("source_color", &data.source_color),
+        ("author_color", &data.author_color),
+    ] {
+        print_option(name, Some((color.r, color.g, color.b)), origin(name));
+    }
 
     println!("\n\n\tContent settings:");
-    println!("\timage_dir: {:?}", data.image_dir.clone());
-    println!("\tquotes_dir: {:?}", data.quotes_dir.clone());
-    println!("\timage_types: {:?}", data.image_types.join(", "));
-    println!("\tinclude_folders: {:?}", data.include_folders.join(", "));
+    print_option("image_dir", Some(&data.image_dir), origin("image_dir"));
+    print_option("quotes_dir", Some(&data.quotes_dir), origin("quotes_dir"));
+    print_option(
+        "image_types",
+        Some(data.image_types.join(", ")),
+        origin("image_types"),
+    );
+    print_option(
+        "sniff_images",
+        Some(data.sniff_images),
+        origin("sniff_images"),
+    );
+    print_option("image_index", data.image_index, origin("image_index"));
+    print_option(
+        "thumbnail_cache_mb",
+        data.thumbnail_cache_mb,
+        origin("thumbnail_cache_mb"),
+    );
+    print_option("crop_to_fit", data.crop_to_fit, origin("crop_to_fit"));
+    let effects: Vec<String> = data.image_effects.iter().map(|e| e.to_string()).collect();
+    print_option(
+        "image_effects",
+        Some(effects.join(", ")),
+        origin("image_effects"),
+    );
+    print_option(
+        "effect_color",
+        data.effect_color.as_ref().map(|c| (c.r, c.g, c.b)),
+        origin("effect_color"),
+    );
+    print_option(
+        "image_filter",
+        Some(data.image_filter.join(", ")),
+        origin("image_filter"),
+    );
+    print_option(
+        "quote_filter",
+        Some(data.quote_filter.join(", ")),
+        origin("quote_filter"),
+    );
+    print_option(
+        "image_selection",
+        data.image_selection,
+        origin("image_selection"),
+    );
+    print_option(
+        "quote_selection",
+        data.quote_selection,
+        origin("quote_selection"),
+    );
+    print_option("avoid_repeats", data.avoid_repeats, origin("avoid_repeats"));
+    print_option("selection", data.selection, origin("selection"));
+    print_option("tag_pairing", data.tag_pairing, origin("tag_pairing"));
+    print_option("nsfw_quotes", Some(data.nsfw_quotes), origin("nsfw_quotes"));
+    print_option(
+        "quote_author",
+        data.quote_author.clone(),
+        origin("quote_author"),
+    );
+    print_option(
+        "quote_tags",
+        Some(data.quote_tags.join(", ")),
+        origin("quote_tags"),
+    );
+    print_option(
+        "quote_source",
+        data.quote_source.clone(),
+        origin("quote_source"),
+    );
+    print_option(
+        "quote_max_lines",
+        data.quote_max_lines,
+        origin("quote_max_lines"),
+    );
+    print_option("quote_grep", data.quote_grep.clone(), origin("quote_grep"));
+    print_option(
+        "image_weights",
+        Some(&data.image_weights),
+        origin("image_weights"),
+    );
+    print_option(
+        "quote_weights",
+        Some(&data.quote_weights),
+        origin("quote_weights"),
+    );
 }
 
-pub fn parse_config() -> NerdcliConfig {
-    if let Some(proj_dirs) = ProjectDirs::from("dev", "jo", "nerdcli") {
+pub fn project_dirs() -> ProjectDirs {
+    match ProjectDirs::from("dev", "jo", "nerdcli") {
         // Linux:   /home/alice/.config/barapp
         // Windows: C:\Users\Alice\AppData\Roaming\Foo Corp\Bar App
         // macOS:   /Users/Alice/Library/Application Support/com.Foo-Corp.Bar-App
+        Some(proj_dirs) => proj_dirs,
+        None => {
+            eprintln!("Could not read config file.",);
 
-        let config_file = proj_dirs.config_dir().join("nerdcli.toml");
-        let config_string = proj_dirs
-            .config_dir()
-            .join("nerdcli.toml")
-            .into_os_string()
-            .into_string()
-            .unwrap();
-
-        let contents = match fs::read_to_string(config_file) {
-            Ok(c) => c,
-            Err(e) => {
-                eprintln!(
-                    "Could not read config file from `{}`: Error: {}",
-                    config_string, e
-                );
-                ask_for_config_creation(proj_dirs);
-                exit(1);
-            }
-        };
-        match toml::from_str::<NerdcliConfig>(&contents) {
-            Ok(d) => {
-                let mut res = d.clone();
-                res.config_base_path = Some(
-                    proj_dirs
-                        .config_dir()
-                        .as_os_str()
-                        .to_str()
-                        .unwrap()
-                        .to_string(),
-                );
-                return res;
-            }
+            eprintln!("Depending on your OS, the config file must be located in a certain position (supposing your username is fry)");
+            eprintln!("\tmacOS:   /Users/fry/Library/Application Support/dev.jo.nerdcli");
+            eprintln!("\tLinux:   /home/fry/.config/nerdcli");
+            eprintln!("\tWindows:   C:\\Users\\fry\\AppData\\Roaming\\jo\\nerdcli");
+            exit(1);
+        }
+    }
+}
 
-            Err(e) => {
-                eprintln!("Unable to parse config file `{}`: {}", config_string, e);
-                exit(1);
-            }
-        };
-    } else {
-        eprintln!("Could not read config file.",);
+/// The directory for the history and the ratings (`~/.local/state/nerdcli` on Linux).
+pub fn state_dir() -> PathBuf {
+    let dirs = project_dirs();
+    dirs.state_dir()
+        .unwrap_or_else(|| dirs.data_local_dir())
+        .to_path_buf()
+}
+
+pub fn config_file_path() -> PathBuf {
+    project_dirs().config_dir().join("nerdcli.toml")
+}
+
+/// Checks if the content of a config file can be used by nerdcli.
+pub fn validate_config(contents: &str) -> Result<(), String> {
+    let mut table = toml::from_str::<Table>(contents).map_err(|e| e.to_string())?;
+    let profiles = take_all_profiles(&mut table)?;
+    let config = Value::Table(table.clone())
+        .try_into::<NerdcliConfig>()
+        .map_err(|e| e.to_string())?;
+    if config.config_version > CONFIG_VERSION {
+        return Err(format!(
+            "config_version {} is not supported (up to {})",
+            config.config_version, CONFIG_VERSION
+        ));
+    }
+
+    for (name, profile) in profiles {
+        let mut merged = table.clone();
+        merged.extend(profile);
+        Value::Table(merged)
+            .try_into::<NerdcliConfig>()
+            .map_err(|e| format!("in profile `{}`: {}", name, e))?;
+    }
+    Ok(())
+}
+
+/// Reads the config file and merges the active profile, the environment variables and the
+/// command line arguments into it. Returns the merged table, the name of the active profile
+/// and the origin of every setting.
+pub fn effective_table(cli: &Cli) -> (Table, Option<String>, BTreeMap<String, ValueOrigin>) {
+    let proj_dirs = project_dirs();
+    let config_file = proj_dirs.config_dir().join("nerdcli.toml");
+    let config_string = config_file.to_string_lossy().into_owned();
+    migrate_config_file(&config_file);
+
+    let contents = match fs::read_to_string(&config_file) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!(
+                "Could not read config file from `{}`: Error: {}",
+                config_string, e
+            );
+            ask_for_config_creation(proj_dirs);
+            exit(1);
+        }
+    };
+
+    let mut table = match toml::from_str::<Table>(&contents) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("Unable to parse config file `{}`: {}", config_string, e);
+            exit(1);
+        }
+    };
+    let profile = take_profile(&mut table, cli.profile.as_ref());
+    let profile_name = profile.as_ref().map(|(name, _)| name.clone());
+    let origins = apply_overrides(&mut table, profile, cli);
+
+    (table, profile_name, origins)
+}
+
+pub fn parse_config(cli: &Cli) -> NerdcliConfig {
+    let (table, profile, origins) = effective_table(cli);
+
+    match Value::Table(table).try_into::<NerdcliConfig>() {
+        Ok(mut res) => {
+            res.config_base_path = Some(
+                project_dirs()
+                    .config_dir()
+                    .as_os_str()
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            );
+            res.profile = profile;
+            res.origins = origins;
+            res
+        }
+
+        Err(e) => {
+            eprintln!(
+                "Unable to parse config file `{}`: {}",
+                config_file_path().display(),
+                e
+            );
+            exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_environment_values_as_toml() {
+        assert_eq!(
+            parse_env_value(Kind::Number, " 42 "),
+            Ok(Value::Integer(42))
+        );
+        assert_eq!(
+            parse_env_value(Kind::Flag, "true"),
+            Ok(Value::Boolean(true))
+        );
+        assert_eq!(
+            parse_env_value(Kind::List, "[\"png\", \"gif\"]"),
+            parse_env_value(Kind::List, "png, gif,")
+        );
+        assert_eq!(
+            parse_env_value(Kind::Table, "{ comics = 2 }")
+                .unwrap()
+                .get("comics"),
+            Some(&Value::Integer(2))
+        );
+    }
+
+    #[test]
+    fn reads_text_values_verbatim() {
+        assert_eq!(
+            parse_env_value(Kind::Text, "\"ROW\""),
+            Ok(Value::String("\"ROW\"".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_the_short_forms_of_flags_and_colors() {
+        assert_eq!(parse_env_value(Kind::Flag, "Yes"), Ok(Value::Boolean(true)));
+        assert_eq!(
+            parse_env_value(Kind::Flag, "off"),
+            Ok(Value::Boolean(false))
+        );
+        let color = parse_env_value(Kind::Color, "#ff8000").unwrap();
+        assert_eq!(color, parse_env_value(Kind::Color, "255, 128, 0").unwrap());
+        assert_eq!(color.get("g"), Some(&Value::Integer(128)));
+    }
+
+    #[test]
+    fn rejects_invalid_environment_values() {
+        assert!(parse_env_value(Kind::Number, "many").is_err());
+        assert!(parse_env_value(Kind::Flag, "maybe").is_err());
+        assert!(parse_env_value(Kind::Color, "red").is_err());
+        assert!(parse_env_value(Kind::Table, "comics").is_err());
+    }
+
+    #[test]
+    fn every_setting_is_overridable_and_in_the_schema() {
+        let config = serde_json::to_value(NerdcliConfig::default()).unwrap();
+        let fields: Vec<&String> = config
+            .as_object()
+            .unwrap()
+            .keys()
+            .filter(|k| *k != "config_version")
+            .collect();
+        let settings: Vec<&str> = SETTINGS.iter().map(|(key, _)| *key).collect();
+        let schema = crate::schema::config_schema();
 
-        eprintln!("Depending on your OS, the config file must be located in a certain position (supposing your username is fry)");
-        eprintln!("\tmacOS:   /Users/fry/Library/Application Support/dev.jo.nerdcli");
-        eprintln!("\tLinux:   /home/fry/.config/nerdcli");
-        eprintln!("\tWindows:   C:\\Users\\fry\\AppData\\Roaming\\jo\\nerdcli");
-        exit(1);
+        for field in &fields {
+            assert!(
+                settings.contains(&field.as_str()),
+                "`{}` is not in SETTINGS",
+                field
+            );
+            assert!(
+                schema["properties"].get(field.as_str()).is_some(),
+                "`{}` is not in the schema",
+                field
+            );
+        }
+        assert_eq!(fields.len(), settings.len(), "SETTINGS has unknown keys");
     }
 }
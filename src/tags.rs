@@ -0,0 +1,59 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use crate::content::ImageSidecar;
+use crate::filters::PathFilter;
+
+/// Name of the file in the image_dir that assigns tags to images by glob patterns, e.g.
+/// `"comics/mignola/**" = ["hellboy", "bprd"]`.
+pub const TAGS_FILE: &str = "tags.toml";
+
+/// Tags are compared case insensitive.
+pub fn normalize(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// The names of the folders between `root` and the file, e.g. `comics` and `hellboy`
+/// for `comics/hellboy/1.png`.
+pub fn folder_tags(root: &Path, file: &Path) -> BTreeSet<String> {
+    file.parent()
+        .and_then(|dir| dir.strip_prefix(root).ok())
+        .map(|dir| {
+            dir.components()
+                .map(|c| normalize(&c.as_os_str().to_string_lossy()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The tags of an image: its folder names, the tags of its sidecar file and of the tags.toml.
+pub fn image_tags(root: &Path, image: &Path, sidecar: &ImageSidecar) -> BTreeSet<String> {
+    let mut tags = folder_tags(root, image);
+    tags.extend(sidecar.tags.iter().map(|t| normalize(t)));
+
+    let Ok(relative) = image.strip_prefix(root) else {
+        return tags;
+    };
+    let tags_file = root.join(TAGS_FILE);
+    let Ok(contents) = fs::read_to_string(&tags_file) else {
+        return tags;
+    };
+    match toml::from_str::<BTreeMap<String, Vec<String>>>(&contents) {
+        Ok(patterns) => {
+            for (pattern, pattern_tags) in patterns {
+                match PathFilter::new(&[pattern]) {
+                    Ok(filter) if filter.is_match(relative) => {
+                        tags.extend(pattern_tags.iter().map(|t| normalize(t)))
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Ignoring a pattern in `{}`: {}", tags_file.display(), e),
+                }
+            }
+        }
+        Err(e) => eprintln!("Ignoring `{}`: {}", tags_file.display(), e),
+    }
+    tags
+}